@@ -0,0 +1,71 @@
+//! Checks that `Game::tick` fits in the time budget of a single tick with
+//! hundreds of players and bullets in a full-size arena.
+//!
+//! Run with: cargo bench --bench tick
+
+use std::time::{Duration, Instant};
+use tokyo::{
+    game::{Game, TICKS_PER_SECOND},
+    models::{GameCommand, GameConfig, GameRules},
+};
+
+const PLAYER_COUNTS: &[u32] = &[50, 200, 500, 2000];
+const WARMUP_TICKS: u32 = 60;
+const MEASURED_TICKS: u32 = 300;
+
+fn bench_tick(num_players: u32) -> (Duration, Duration, usize) {
+    let mut game = Game::new(GameConfig {
+        bound_x: 3500.0,
+        bound_y: 2500.0,
+        seed: Some(42),
+        rules: GameRules::default(),
+        obstacles: vec![],
+    });
+
+    for id in 0..num_players {
+        game.add_player(id);
+        let _ = game.handle_cmd(id, GameCommand::Throttle(1.0));
+    }
+
+    let mut total = Duration::default();
+    let mut worst = Duration::default();
+    let mut max_bullets = 0;
+
+    for tick in 0..WARMUP_TICKS + MEASURED_TICKS {
+        // Keep everybody turning and firing, roughly at the rate a client can
+        for id in 0..num_players {
+            let command = match (tick + id) % 3 {
+                0 => GameCommand::Rotate((tick + id) as f32 * 0.1),
+                1 => GameCommand::Throttle(1.0),
+                _ => GameCommand::Fire,
+            };
+            let _ = game.handle_cmd(id, command);
+        }
+
+        let start = Instant::now();
+        game.tick();
+        let elapsed = start.elapsed();
+
+        if tick >= WARMUP_TICKS {
+            total += elapsed;
+            worst = worst.max(elapsed);
+            max_bullets = max_bullets.max(game.state.bullets.len());
+        }
+    }
+
+    (total / MEASURED_TICKS, worst, max_bullets)
+}
+
+fn main() {
+    let budget = Duration::from_secs_f32(1.0 / TICKS_PER_SECOND);
+
+    for &num_players in PLAYER_COUNTS {
+        let (mean, worst, max_bullets) = bench_tick(num_players);
+        println!(
+            "{:>4} players, up to {:>4} bullets: mean {:?}, worst {:?} (budget {:?})",
+            num_players, max_bullets, mean, worst, budget
+        );
+
+        assert!(mean < budget, "Game::tick is over budget with {} players", num_players);
+    }
+}
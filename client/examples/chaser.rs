@@ -6,7 +6,6 @@
 /// which you may or may not like. Please see the documentation in the `behavior`
 /// mod for more details.
 use std::env;
-use std::time::Instant;
 use tokyo::{
     self,
     analyzer::Analyzer,
@@ -38,7 +37,7 @@ fn chase() -> Box<dyn Behavior> {
 
 impl Handler for Player {
     fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
-        self.analyzer.push_state(state, Instant::now());
+        self.analyzer.push_state(state);
 
         if let Some(command) = self.current_behavior.next_command(&self.analyzer) {
             Some(command)
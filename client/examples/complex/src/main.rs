@@ -75,7 +75,7 @@ impl Player {
 impl Handler for Player {
     fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
         let now = Instant::now();
-        self.analyzer.push_state(state, now);
+        self.analyzer.push_state(state);
 
         let next_command = self.current_behavior.behavior.next_command(&self.analyzer);
         if let Some(next_behavior) = self.strategy.next_behavior(&self.analyzer) {
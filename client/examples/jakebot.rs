@@ -1,6 +1,6 @@
 use euclid::Angle;
 use std::env;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokyo::{self, analyzer::Analyzer, geom::*, models::*, Handler};
 
 enum State {
@@ -38,7 +38,7 @@ impl Player {
 
 impl Handler for Player {
     fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
-        self.analyzer.push_state(state, Instant::now());
+        self.analyzer.push_state(state);
 
         let me = self.analyzer.own_player();
         if let Some(State::Dodging(count)) = self.state {
@@ -0,0 +1,66 @@
+/// An example of running bots against each other offline, without a server.
+/// The match is played as fast as the CPU allows, so it can be scripted into
+/// CI to check that a change to your bot makes it stronger.
+use std::{env, time::Duration};
+use tokyo::{models::*, simulation::Simulation, Handler};
+
+/// Spins in place and fires all the time.
+#[derive(Default)]
+struct Spinner {
+    angle: f32,
+    counter: u32,
+}
+
+impl Handler for Spinner {
+    fn tick(&mut self, _: &ClientState) -> Option<GameCommand> {
+        self.counter += 1;
+        self.angle += 0.2;
+
+        Some(match self.counter % 2 {
+            0 => GameCommand::Rotate(self.angle),
+            _ => GameCommand::Fire,
+        })
+    }
+}
+
+/// Drives towards the first other player it sees, firing on the way.
+#[derive(Default)]
+struct Chaser {
+    counter: u32,
+}
+
+impl Handler for Chaser {
+    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
+        self.counter += 1;
+
+        let players = &state.game_state.players;
+        let me = players.iter().find(|player| player.id == state.id)?;
+        let target = players.iter().find(|player| player.id != state.id)?;
+
+        Some(match self.counter % 3 {
+            0 => GameCommand::Rotate((target.y - me.y).atan2(target.x - me.x)),
+            1 => GameCommand::Throttle(1.0),
+            _ => GameCommand::Fire,
+        })
+    }
+}
+
+fn main() {
+    let seed = env::var("SEED").ok().and_then(|seed| seed.parse().ok());
+    let config = GameConfig {
+        bound_x: 1200.0,
+        bound_y: 800.0,
+        seed,
+        rules: GameRules::default(),
+        obstacles: vec![],
+    };
+    let mut simulation = Simulation::new(config);
+
+    simulation.add_bot("spinner", Spinner::default());
+    simulation.add_bot("chaser", Chaser::default());
+    simulation.add_bot("another chaser", Chaser::default());
+
+    for (rank, score) in simulation.run_for(Duration::from_secs(300)).iter().enumerate() {
+        println!("{}. {} - {}", rank + 1, score.name, score.score);
+    }
+}
@@ -1,12 +1,10 @@
 use crate::{
     analyzer::{bullet::Bullet, player::Player},
     geom::*,
-    models::ClientState,
-};
-use std::{
-    collections::HashMap,
-    time::{Duration, Instant},
+    game::ticks_to_duration,
+    models::{ClientState, GameStatus, Obstacle},
 };
+use std::{collections::HashMap, time::Duration};
 
 pub mod bullet;
 pub mod player;
@@ -27,20 +25,25 @@ pub struct Item {
 /// let mut analyzer = Analyzer::default();
 ///
 /// // Call push_state at each tick.
-/// analyzer.push_state(state, Instant::now());
+/// analyzer.push_state(state);
 ///
 /// // e.g. Find the closest player to yourself.
 /// if let Some(player) = analyzer.player_closest() {
 ///     do_something_with(player);
 /// }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Analyzer {
     own_player_id: u32,
     players: HashMap<u32, Player>,
+    teams: HashMap<u32, u32>, // Player ID -> team ID, in team games
     bullets: Vec<Bullet>,
     items: Vec<Item>,
-    last_update: Instant,
+    obstacles: Vec<Obstacle>,
+    tick: u64,
+    status: GameStatus,
+    elapsed: Duration,
+    remaining: Option<Duration>,
 }
 
 impl PointExt for Item {
@@ -49,39 +52,62 @@ impl PointExt for Item {
     }
 }
 
-impl Default for Analyzer {
-    fn default() -> Self {
-        Self {
-            own_player_id: 0,
-            players: HashMap::new(),
-            bullets: Vec::new(),
-            items: Vec::new(),
-            last_update: Instant::now(),
-        }
-    }
-}
-
 impl Analyzer {
-    /// This method needs to be called at every client tick.
-    pub fn push_state(&mut self, state: &ClientState, time: Instant) {
+    /// This method needs to be called at every client tick. The states are
+    /// timed by their game tick, so the network jitter doesn't skew velocities.
+    pub fn push_state(&mut self, state: &ClientState) {
         self.own_player_id = state.id;
+        let tick = state.game_state.tick;
+        let time = ticks_to_duration(tick, state.game_state.tick_rate);
+        let rules = &state.game_state.rules;
 
         let mut players = HashMap::new();
         for player_state in state.game_state.players.iter() {
             let player = if let Some(mut prev_player) = self.players.remove(&player_state.id) {
-                prev_player.push_state(&player_state, &state.game_state.scoreboard, time);
+                prev_player.push_state(&player_state, &state.game_state.scoreboard, rules, time);
                 prev_player
             } else {
-                Player::with_state(&player_state, &state.game_state.scoreboard, time)
+                Player::with_state(&player_state, &state.game_state.scoreboard, rules, time)
             };
             players.insert(player.id, player);
         }
         self.players = players;
+        self.teams = state
+            .game_state
+            .teams
+            .iter()
+            .flat_map(|team| team.players.iter().map(move |&player_id| (player_id, team.id)))
+            .collect();
         self.items = state.game_state.items.iter().map(|i| Item { position: Point::new(i.x, i.y) }).collect();
 
         self.bullets = state.game_state.bullets.iter().map(|state| Bullet::new(&state)).collect();
+        self.obstacles.clone_from(&state.game_state.obstacles);
+
+        self.tick = tick;
+        self.status = state.game_state.status;
+        self.elapsed = Duration::from_secs_f32(state.game_state.elapsed_seconds);
+        self.remaining = state.game_state.remaining_seconds.map(Duration::from_secs_f32);
+    }
+
+    /// Returns the game tick of the latest state pushed.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Returns the status of the match.
+    pub fn status(&self) -> GameStatus {
+        self.status
+    }
+
+    /// Returns the time played in the match so far, pauses excluded.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
 
-        self.last_update = time;
+    /// Returns the time left before the match ends, or `None` if it has no
+    /// time limit.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining
     }
 
     pub fn item_closest(&self) -> Option<&Item> {
@@ -112,6 +138,49 @@ impl Analyzer {
         self.players.values().filter(move |player| player.id != self.own_player_id)
     }
 
+    /// Returns the ID of the team of a `Player`, or `None` if it plays for
+    /// itself, as everybody does outside of team games.
+    pub fn team(&self, player_id: u32) -> Option<u32> {
+        self.teams.get(&player_id).copied()
+    }
+
+    /// Returns an `Iterator` of the `Player`s of your own team, excluding
+    /// yourself. Empty outside of team games.
+    pub fn teammates(&self) -> impl Iterator<Item = &Player> {
+        let own_team = self.team(self.own_player_id);
+        self.other_players()
+            .filter(move |player| own_team.is_some() && self.team(player.id) == own_team)
+    }
+
+    /// Returns an `Iterator` of the `Player`s you're up against: those of the
+    /// other teams in team games, everybody else otherwise.
+    pub fn enemies(&self) -> impl Iterator<Item = &Player> {
+        let own_team = self.team(self.own_player_id);
+        self.other_players()
+            .filter(move |player| own_team.is_none() || self.team(player.id) != own_team)
+    }
+
+    /// Returns the obstacles of the arena.
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// Returns true if a circle of `radius` at `point` would overlap an
+    /// obstacle, i.e. a ship can't be there.
+    pub fn is_blocked(&self, point: &Point, radius: f32) -> bool {
+        self.obstacles.iter().any(|obstacle| obstacle.overlaps(point.x, point.y, radius))
+    }
+
+    /// Returns true if no obstacle stands between your own `Player` and the
+    /// given point, so a bullet fired at it can get there.
+    pub fn in_line_of_sight(&self, target: &dyn PointExt) -> bool {
+        let start = *self.own_player().point();
+        let motion = *target.point() - start;
+        self.obstacles
+            .iter()
+            .all(|obstacle| obstacle.sweep((start.x, start.y), (motion.x, motion.y), 0.0).is_none())
+    }
+
     /// Returns a `Player`, who is closest to the current position of your own
     /// `Player`. None if you are the only `Player`.
     pub fn player_closest(&self) -> Option<&Player> {
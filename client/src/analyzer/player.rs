@@ -1,12 +1,9 @@
 use crate::{
     analyzer::ANALYSIS_INTERVAL,
     geom::*,
-    models::{self, PlayerState, PLAYER_BASE_SPEED, PLAYER_MIN_THROTTLE},
-};
-use std::{
-    collections::HashMap,
-    time::{Duration, Instant},
+    models::{self, GameRules, PlayerState, PLAYER_MIN_THROTTLE},
 };
+use std::{collections::HashMap, time::Duration};
 
 #[derive(Debug, Clone)]
 /// `Player` struct contains the past and the current states of a single player
@@ -26,11 +23,18 @@ pub struct Player {
 }
 
 impl Player {
-    /// Creates a new `Player` based on the given `state`.
-    pub fn with_state(state: &PlayerState, scoreboard: &HashMap<u32, u32>, time: Instant) -> Self {
+    /// Creates a new `Player` based on the given `state`, moving as fast as
+    /// the `rules` of the game allow.
+    /// The `time` is the game time of the state, see `Trajectory`.
+    pub fn with_state(
+        state: &PlayerState,
+        scoreboard: &HashMap<u32, u32>,
+        rules: &GameRules,
+        time: Duration,
+    ) -> Self {
         let angle = Radian::new(state.angle);
         let position = Point::new(state.x, state.y);
-        let velocity = Vector::with_angle(angle) * state.throttle * PLAYER_BASE_SPEED;
+        let velocity = Vector::with_angle(angle) * state.throttle * state.speed(rules);
 
         let mut trajectory = Trajectory::default();
         trajectory.push(position.clone(), time);
@@ -57,14 +61,15 @@ impl Player {
         &mut self,
         state: &PlayerState,
         scoreboard: &HashMap<u32, u32>,
-        time: Instant,
+        rules: &GameRules,
+        time: Duration,
     ) {
         assert_eq!(self.id, state.id);
 
         self.angle = Radian::new(state.angle);
         self.throttle = state.throttle;
         self.position = Point::new(state.x, state.y);
-        self.velocity = Vector::with_angle(self.angle) * state.throttle * PLAYER_BASE_SPEED;
+        self.velocity = Vector::with_angle(self.angle) * state.throttle * state.speed(rules);
         self.trajectory.push(self.position.clone(), time);
         self.score_history.push(*scoreboard.get(&state.id).unwrap_or(&0), time);
     }
@@ -134,17 +139,25 @@ impl Moving for Player {
     }
 }
 
-/// `Trajectory` contains the past positions of a `Player`. You may want to use
-/// it to infer the move behavior and logic of a `Player` of your interest.
+/// `Trajectory` contains the past positions of a `Player`, along with the game
+/// time at which they were observed, i.e. the time since the start of the game
+/// as counted by its ticks. You may want to use it to infer the move behavior
+/// and logic of a `Player` of your interest.
 #[derive(Debug, Default, Clone)]
 pub struct Trajectory {
-    pub positions: Vec<(Point, Instant)>,
+    pub positions: Vec<(Point, Duration)>,
 }
 
 impl Trajectory {
-    /// Pushes a new state to the `Trajectory`.
-    pub fn push(&mut self, position: Point, time: Instant) {
-        self.positions.push((position, time));
+    /// Pushes a new state to the `Trajectory`. A state of the same game time as
+    /// the last one replaces it, and one from before it starts the `Trajectory`
+    /// over: that's a new game, like the next room of the matchmaking queue.
+    pub fn push(&mut self, position: Point, time: Duration) {
+        match self.positions.last_mut() {
+            Some(last) if last.1 == time => *last = (position, time),
+            Some(last) if last.1 > time => self.positions = vec![(position, time)],
+            _ => self.positions.push((position, time)),
+        }
     }
 
     /// Returns the last known position of the `Trajectory`.
@@ -169,7 +182,10 @@ impl Trajectory {
     pub fn last_velocity(&self) -> Vector {
         let (last_position, last_time) = self.positions.last().unwrap();
         if let Some((prev_position, prev_time)) = self.positions.get(self.positions.len() - 2) {
-            prev_position.velocity_to(last_position, *last_time - *prev_time)
+            match last_time.checked_sub(*prev_time) {
+                Some(interval) => prev_position.velocity_to(last_position, interval),
+                None => Vector::zero(),
+            }
         } else {
             // No idea, just return zeros.
             Vector::zero()
@@ -184,8 +200,9 @@ impl Trajectory {
             .positions
             .iter()
             .zip(self.positions.iter().skip(1))
-            .map(|((prev_position, prev_time), (position, time))| {
-                prev_position.velocity_to(position, *time - *prev_time).abs()
+            .filter_map(|((prev_position, prev_time), (position, time))| {
+                let interval = time.checked_sub(*prev_time)?;
+                Some(prev_position.velocity_to(position, interval).abs())
             })
             .fold((0, Vector::zero()), |acc, next| (acc.0 + 1, acc.1 + next));
 
@@ -202,13 +219,18 @@ impl Trajectory {
 /// scoring in the future, instead of just looking at the current scores.
 #[derive(Debug, Default, Clone)]
 pub struct ScoreHistory {
-    inner: Vec<(u32, Instant)>,
+    inner: Vec<(u32, Duration)>,
 }
 
 impl ScoreHistory {
-    /// Pushes a new state to the `ScoreHistory`.
-    pub fn push(&mut self, score: u32, time: Instant) {
-        self.inner.push((score, time));
+    /// Pushes a new state to the `ScoreHistory`. A state of the same game time
+    /// as the last one replaces it, and one from before it starts a new game.
+    pub fn push(&mut self, score: u32, time: Duration) {
+        match self.inner.last_mut() {
+            Some(last) if last.1 == time => *last = (score, time),
+            Some(last) if last.1 > time => self.inner = vec![(score, time)],
+            _ => self.inner.push((score, time)),
+        }
     }
 
     /// Returns the current score of the `Player`.
@@ -222,21 +244,21 @@ impl ScoreHistory {
         self.inner.last().unwrap().0
     }
 
-    /// Returns the total score earned since the particular `past_time`.
+    /// Returns the total score earned since the game time `past_time`.
     ///
     /// # Panics
     ///
     /// It panics if the `push()` method has not been called before. It should
     /// not happen as long as you are calling `Analyzer::push_state()` at the
     /// beginning of each `tick()` method.
-    pub fn score_since(&self, past_time: Instant) -> u32 {
+    pub fn score_since(&self, past_time: Duration) -> u32 {
         let start_score = self
             .inner
             .iter()
             .rev()
             .find_map(|(score, time)| if *time <= past_time { Some(*score) } else { None })
             .unwrap_or(0u32);
-        self.last_score() - start_score
+        self.last_score().saturating_sub(start_score)
     }
 
     /// Returns the projected score in a particular time in the future specified
@@ -249,7 +271,8 @@ impl ScoreHistory {
     /// beginning of each `tick()` method.
     pub fn project(&self, after: Duration) -> u32 {
         let past_duration = Duration::from_secs(10); // configurable
-        let past_score = self.score_since(Instant::now() - past_duration);
+        let last_time = self.inner.last().unwrap().1;
+        let past_score = self.score_since(last_time.saturating_sub(past_duration));
         self.last_score()
             + (past_score as f32 * (after.as_millis() as f32 / past_duration.as_millis() as f32))
                 as u32
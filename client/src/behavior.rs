@@ -22,7 +22,7 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 /// ```
 /// impl Handlar for Player {
 ///     fn tick(...) {
-///         self.analyzer.push_state(state, Instant::now());
+///         self.analyzer.push_state(state);
 ///
 ///         if let Some(next_command) = self.current_behavior.next_command(&self.analyzer) {
 ///             return Some(next_command);
@@ -56,7 +56,7 @@ use std::{collections::VecDeque, fmt::Debug, time::Duration};
 /// ```
 /// impl Handlar for Player {
 ///     fn tick(...) {
-///         self.analyzer.push_state(state, Instant::now());
+///         self.analyzer.push_state(state);
 ///
 ///         // Find one of the bullets that are colliding within a second.
 ///         if let Some(bullet) = self.analyzer.bullets_colliding(Duration::from_secs(1)).next() {
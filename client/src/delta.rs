@@ -0,0 +1,231 @@
+//! Delta compression of the game state. Instead of a full `GameState` every
+//! tick, the server can send the entities that were added, changed or removed
+//! since the previous tick, which the client applies to its own copy.
+//!
+//! Bullets fly in a straight line, so both sides move them by a tick before
+//! comparing. Only the bullets that didn't end up where expected are sent.
+
+use crate::{
+    game::advance_bullet,
+    models::{BulletState, DeadPlayer, EntityDelta, GameState, GameStateDelta, Item, PlayerState},
+};
+use std::collections::{HashMap, HashSet};
+
+/// Something in the game state that can be told apart from the others by ID.
+trait Entity: Clone + PartialEq {
+    fn id(&self) -> u32;
+}
+
+impl Entity for PlayerState {
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Entity for DeadPlayer {
+    fn id(&self) -> u32 {
+        self.player.id
+    }
+}
+
+impl Entity for BulletState {
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Entity for Item {
+    fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Entity for (u32, u32) {
+    fn id(&self) -> u32 {
+        self.0
+    }
+}
+
+impl GameStateDelta {
+    /// Lists the changes needed to turn `previous` into `current`.
+    pub fn between(seq: u64, previous: &GameState, current: &GameState) -> Self {
+        let dt = 1.0 / current.tick_rate;
+        let mut predicted_bullets = previous.bullets.clone();
+        predicted_bullets.iter_mut().for_each(|bullet| advance_bullet(bullet, dt));
+
+        Self {
+            seq,
+            tick: current.tick,
+            server_time: current.server_time,
+            status: current.status,
+            elapsed_seconds: current.elapsed_seconds,
+            remaining_seconds: current.remaining_seconds,
+            players: diff(&previous.players, &current.players),
+            items: diff(&previous.items, &current.items),
+            dead: diff(&previous.dead, &current.dead),
+            bullets: diff(&predicted_bullets, &current.bullets),
+            scoreboard: diff(&scoreboard_entries(previous), &scoreboard_entries(current)),
+            teams: (current.teams != previous.teams).then(|| current.teams.clone()),
+        }
+    }
+}
+
+impl GameState {
+    /// Applies the changes of a delta built on top of this state.
+    pub fn apply_delta(&mut self, delta: GameStateDelta) {
+        self.tick = delta.tick;
+        self.server_time = delta.server_time;
+        self.status = delta.status;
+        self.elapsed_seconds = delta.elapsed_seconds;
+        self.remaining_seconds = delta.remaining_seconds;
+        apply(&mut self.players, delta.players);
+        apply(&mut self.items, delta.items);
+        apply(&mut self.dead, delta.dead);
+
+        let dt = 1.0 / self.tick_rate;
+        self.bullets.iter_mut().for_each(|bullet| advance_bullet(bullet, dt));
+        apply(&mut self.bullets, delta.bullets);
+
+        let mut scoreboard = scoreboard_entries(self);
+        apply(&mut scoreboard, delta.scoreboard);
+        self.scoreboard = scoreboard.into_iter().collect();
+        if let Some(teams) = delta.teams {
+            self.teams = teams;
+        }
+    }
+}
+
+fn scoreboard_entries(state: &GameState) -> Vec<(u32, u32)> {
+    state.scoreboard.iter().map(|(&id, &score)| (id, score)).collect()
+}
+
+fn diff<T: Entity>(previous: &[T], current: &[T]) -> EntityDelta<T> {
+    let previous_by_id: HashMap<u32, &T> =
+        previous.iter().map(|entity| (entity.id(), entity)).collect();
+    let current_ids: HashSet<u32> = current.iter().map(Entity::id).collect();
+
+    let mut added = vec![];
+    let mut changed = vec![];
+
+    for entity in current {
+        match previous_by_id.get(&entity.id()) {
+            None => added.push(entity.clone()),
+            Some(&old) if old != entity => changed.push(entity.clone()),
+            Some(_) => {},
+        }
+    }
+
+    let removed = previous.iter().map(Entity::id).filter(|id| !current_ids.contains(id)).collect();
+
+    EntityDelta { added, changed, removed }
+}
+
+fn apply<T: Entity>(entities: &mut Vec<T>, delta: EntityDelta<T>) {
+    if !delta.removed.is_empty() {
+        let removed: HashSet<u32> = delta.removed.into_iter().collect();
+        entities.retain(|entity| !removed.contains(&entity.id()));
+    }
+
+    if !delta.changed.is_empty() {
+        let mut changed: HashMap<u32, T> =
+            delta.changed.into_iter().map(|entity| (entity.id(), entity)).collect();
+
+        for entity in entities.iter_mut() {
+            if let Some(new_entity) = changed.remove(&entity.id()) {
+                *entity = new_entity;
+            }
+        }
+    }
+
+    entities.extend(delta.added);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game::Game,
+        models::{GameCommand, GameConfig, GameRules, Obstacle},
+    };
+
+    /// Applies the delta between `previous` and `current` to `previous`, which
+    /// must turn it into `current`.
+    fn assert_round_trip(previous: &GameState, current: &GameState) -> GameStateDelta {
+        let delta = GameStateDelta::between(1, previous, current);
+        let mut applied = previous.clone();
+        applied.apply_delta(delta.clone());
+
+        // `GameState` isn't `PartialEq`, its JSON compares the same
+        assert_eq!(
+            serde_json::to_value(&applied).unwrap(),
+            serde_json::to_value(current).unwrap(),
+            "at tick {}",
+            current.tick
+        );
+        delta
+    }
+
+    fn bullet(id: u32, x: f32, angle: f32) -> BulletState {
+        BulletState { id, player_id: 1, angle, x, y: 100.0, radius: 2.0, speed: 100.0 }
+    }
+
+    #[test]
+    fn only_sends_the_bullets_off_their_predicted_path() {
+        let mut previous = GameState::new((400.0, 400.0), 10.0);
+        previous.bullets = vec![bullet(1, 50.0, 0.0), bullet(2, 100.0, 0.0), bullet(3, 150.0, 0.0)];
+
+        let mut current = previous.clone();
+        current.tick = 1;
+        current.bullets.iter_mut().for_each(|bullet| advance_bullet(bullet, 0.1));
+        current.bullets.remove(1); // Hit something
+        current.bullets[1].angle = std::f32::consts::PI; // Bounced back
+        current.bullets.push(bullet(4, 200.0, 1.0)); // Just fired
+
+        let delta = assert_round_trip(&previous, &current);
+        let ids = |bullets: &[BulletState]| bullets.iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(ids(&delta.bullets.added), vec![4]);
+        assert_eq!(ids(&delta.bullets.changed), vec![3]);
+        assert_eq!(delta.bullets.removed, vec![2]);
+    }
+
+    #[test]
+    fn round_trips_a_whole_game() {
+        let mut game = Game::new(GameConfig {
+            bound_x: 600.0,
+            bound_y: 400.0,
+            seed: Some(3),
+            rules: GameRules::default(),
+            obstacles: vec![Obstacle::Circle {
+                x: 300.0,
+                y: 200.0,
+                radius: 50.0,
+                reflective: true,
+            }],
+        });
+        for player_id in 0..6 {
+            game.add_player(player_id);
+        }
+
+        let (mut added, mut changed, mut removed, mut predicted) = (0, 0, 0, 0);
+        for tick in 0..500 {
+            let previous = game.state.clone();
+            for player_id in 0..6 {
+                let angle = (tick * (player_id + 1)) as f32 * 0.1;
+                let _ = game.handle_cmd(player_id, GameCommand::Rotate(angle));
+                let _ = game.handle_cmd(player_id, GameCommand::Throttle(0.5));
+                let _ = game.handle_cmd(player_id, GameCommand::Fire);
+            }
+            game.tick();
+
+            let delta = assert_round_trip(&previous, &game.state);
+            added += delta.bullets.added.len();
+            changed += delta.bullets.changed.len();
+            removed += delta.bullets.removed.len();
+            predicted +=
+                game.state.bullets.len() - delta.bullets.added.len() - delta.bullets.changed.len();
+        }
+
+        // Bullets spawned, died, bounced and flew as predicted along the way
+        assert!(added > 0 && changed > 0 && removed > 0 && predicted > 0);
+    }
+}
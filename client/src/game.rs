@@ -0,0 +1,1078 @@
+//! The game simulation run by the server. It has no notion of networking or
+//! wall-clock time, so it can also be driven offline, see the `simulation` mod.
+
+use crate::models::{
+    BulletState, CommandError, DeadPlayer, FriendlyFire, GameCommand, GameConfig, GameRules,
+    GameState, Item, Obstacle, PlayerState, PlayerStats, Team, BULLET_BASE_LIMIT,
+    BULLET_BASE_RADIUS, BULLET_BASE_SPEED, PLAYER_BASE_RADIUS, PLAYER_BASE_SPEED,
+};
+use grid::SpatialGrid;
+use log::{info, warn};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{collections::HashMap, time::Duration};
+
+mod grid;
+
+// The defaults of the `GameRules`
+
+const DEAD_PUNISH: Duration = Duration::from_secs(3);
+
+pub const TICKS_PER_SECOND: f32 = 30.0;
+
+const MAX_CONCURRENT_ITEMS: usize = 20;
+const ITEM_SPAWN_TIME: Duration = Duration::from_secs(5);
+
+// Time until you start accruing points for surviving
+const SURVIVAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Interval for accruing points after reaching the threshold
+const SURVIVAL_POINT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Time between everybody being ready and the start of the match, only used by the server
+const COUNTDOWN: Duration = Duration::from_secs(3);
+
+// Time a disconnected player keeps their place in the game, only used by the server
+const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+
+// Size of the cells of the broad phase grid, in pixels
+const GRID_CELL_SIZE: f32 = 100.0;
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            tick_rate: TICKS_PER_SECOND,
+            respawn_delay_seconds: DEAD_PUNISH.as_secs_f32(),
+            item_spawn_interval_seconds: ITEM_SPAWN_TIME.as_secs_f32(),
+            max_items: MAX_CONCURRENT_ITEMS as u32,
+            survival_timeout_seconds: SURVIVAL_TIMEOUT.as_secs_f32(),
+            survival_point_interval_seconds: SURVIVAL_POINT_INTERVAL.as_secs_f32(),
+            survival_points: 1,
+            player_radius: PLAYER_BASE_RADIUS,
+            player_speed: PLAYER_BASE_SPEED,
+            bullet_radius: BULLET_BASE_RADIUS,
+            bullet_speed: BULLET_BASE_SPEED,
+            bullet_limit: BULLET_BASE_LIMIT,
+            countdown_seconds: COUNTDOWN.as_secs_f32(),
+            reconnect_grace_seconds: RECONNECT_GRACE.as_secs_f32(),
+            friendly_fire: FriendlyFire::default(),
+        }
+    }
+}
+
+pub trait Triangle {
+    fn x(&self) -> f32;
+    fn y(&self) -> f32;
+    fn angle(&self) -> f32;
+    fn radius(&self) -> f32;
+
+    fn is_colliding(&self, other: &dyn Triangle) -> bool {
+        let d_x = other.x() - self.x();
+        let d_y = other.y() - self.y();
+        let d_r = other.radius() + self.radius();
+        let squared_dist = d_x * d_x + d_y * d_y;
+        let squared_radii = d_r * d_r;
+
+        squared_dist < squared_radii
+    }
+}
+
+/// Whether two circles, moving in a straight line from `start` and
+/// `other_start` by `motion` and `other_motion`, come closer than the sum of
+/// their radii on the way.
+fn sweep_circles(
+    start: (f32, f32),
+    motion: (f32, f32),
+    other_start: (f32, f32),
+    other_motion: (f32, f32),
+    radii: f32,
+) -> bool {
+    // In the frame of `other`, only `self` moves, along a segment
+    let (d_x, d_y) = (motion.0 - other_motion.0, motion.1 - other_motion.1);
+    let start_x = start.0 - other_start.0;
+    let start_y = start.1 - other_start.1;
+
+    // Closest point of the segment to `other`
+    let squared_length = d_x * d_x + d_y * d_y;
+    let t = if squared_length > 0.0 {
+        (-(start_x * d_x + start_y * d_y) / squared_length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let closest_x = start_x + d_x * t;
+    let closest_y = start_y + d_y * t;
+
+    closest_x * closest_x + closest_y * closest_y < radii * radii
+}
+
+impl Triangle for PlayerState {
+    fn x(&self) -> f32 {
+        self.x
+    }
+
+    fn y(&self) -> f32 {
+        self.y
+    }
+
+    fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Triangle for BulletState {
+    fn x(&self) -> f32 {
+        self.x
+    }
+
+    fn y(&self) -> f32 {
+        self.y
+    }
+
+    fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Triangle for Item {
+    fn x(&self) -> f32 {
+        self.x
+    }
+
+    fn y(&self) -> f32 {
+        self.y
+    }
+
+    fn angle(&self) -> f32 {
+        0.0
+    }
+
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
+impl Obstacle {
+    /// Whether a circle of `radius` centered on (x, y) overlaps the obstacle.
+    pub fn overlaps(&self, x: f32, y: f32, radius: f32) -> bool {
+        self.push_out(x, y, radius).is_some()
+    }
+
+    /// Where a circle of `radius` centered on (x, y) has to move to stop
+    /// overlapping the obstacle, along the shortest way out. None when it
+    /// doesn't overlap it.
+    pub fn push_out(&self, x: f32, y: f32, radius: f32) -> Option<(f32, f32)> {
+        match *self {
+            Obstacle::Circle { x: center_x, y: center_y, radius: obstacle_radius, .. } => {
+                let (d_x, d_y) = (x - center_x, y - center_y);
+                let distance = (d_x * d_x + d_y * d_y).sqrt();
+                let min_distance = obstacle_radius + radius;
+                if distance >= min_distance {
+                    return None;
+                }
+
+                let (normal_x, normal_y) =
+                    if distance > 0.0 { (d_x / distance, d_y / distance) } else { (1.0, 0.0) };
+                Some((center_x + normal_x * min_distance, center_y + normal_y * min_distance))
+            },
+            Obstacle::Wall { width, height, .. } => {
+                let (local_x, local_y) = self.local_point(x, y);
+                let (half_width, half_height) = (width / 2.0, height / 2.0);
+                let closest_x = local_x.clamp(-half_width, half_width);
+                let closest_y = local_y.clamp(-half_height, half_height);
+                let (d_x, d_y) = (local_x - closest_x, local_y - closest_y);
+                let distance = (d_x * d_x + d_y * d_y).sqrt();
+
+                let (out_x, out_y) = if distance > 0.0 {
+                    if distance >= radius {
+                        return None;
+                    }
+                    (closest_x + d_x / distance * radius, closest_y + d_y / distance * radius)
+                } else if half_width - local_x.abs() < half_height - local_y.abs() {
+                    // The center is inside, out through the closest side
+                    ((half_width + radius).copysign(local_x), local_y)
+                } else {
+                    (local_x, (half_height + radius).copysign(local_y))
+                };
+                Some(self.world_point(out_x, out_y))
+            },
+        }
+    }
+
+    /// When a circle of `radius` starting at (x, y) and moving by `motion`
+    /// first touches the obstacle, as a fraction of `motion`, along with the
+    /// normal of the obstacle's surface there. Walls are grown by `radius`
+    /// with square corners, which is close enough for bullets.
+    pub(crate) fn sweep(
+        &self,
+        (x, y): (f32, f32),
+        motion: (f32, f32),
+        radius: f32,
+    ) -> Option<(f32, (f32, f32))> {
+        match *self {
+            Obstacle::Circle { x: center_x, y: center_y, radius: obstacle_radius, .. } => {
+                let (start_x, start_y) = (x - center_x, y - center_y);
+                let min_distance = obstacle_radius + radius;
+                let c = start_x * start_x + start_y * start_y - min_distance * min_distance;
+                let t = if c < 0.0 {
+                    0.0
+                } else {
+                    let a = motion.0 * motion.0 + motion.1 * motion.1;
+                    let b = start_x * motion.0 + start_y * motion.1;
+                    let discriminant = b * b - a * c;
+                    if a == 0.0 || b >= 0.0 || discriminant < 0.0 {
+                        return None;
+                    }
+                    (-b - discriminant.sqrt()) / a
+                };
+                if t > 1.0 {
+                    return None;
+                }
+
+                let (hit_x, hit_y) = (start_x + motion.0 * t, start_y + motion.1 * t);
+                let length = (hit_x * hit_x + hit_y * hit_y).sqrt();
+                let normal =
+                    if length > 0.0 { (hit_x / length, hit_y / length) } else { (1.0, 0.0) };
+                Some((t, normal))
+            },
+            Obstacle::Wall { width, height, angle, .. } => {
+                let (start_x, start_y) = self.local_point(x, y);
+                let (motion_x, motion_y) = rotate(motion, -angle);
+                let (start, motion) = ([start_x, start_y], [motion_x, motion_y]);
+                let half_sizes = [width / 2.0 + radius, height / 2.0 + radius];
+
+                // The slab method, keeping the axis through which the box is entered
+                let (mut t_enter, mut t_exit, mut axis) = (f32::NEG_INFINITY, f32::INFINITY, 0);
+                for index in 0..2 {
+                    let half_size = half_sizes[index];
+                    if motion[index] == 0.0 {
+                        if start[index].abs() >= half_size {
+                            return None;
+                        }
+                        continue;
+                    }
+
+                    let t_near =
+                        (-half_size.copysign(motion[index]) - start[index]) / motion[index];
+                    let t_far = (half_size.copysign(motion[index]) - start[index]) / motion[index];
+                    if t_near > t_enter {
+                        t_enter = t_near;
+                        axis = index;
+                    }
+                    t_exit = t_exit.min(t_far);
+                }
+                if t_enter > t_exit || t_exit < 0.0 || t_enter > 1.0 {
+                    return None;
+                }
+
+                // Facing the way the box is entered, or out through the
+                // closest side when already inside
+                let side = if t_enter < 0.0 {
+                    let depths = [half_sizes[0] - start[0].abs(), half_sizes[1] - start[1].abs()];
+                    axis = if depths[0] < depths[1] { 0 } else { 1 };
+                    start[axis]
+                } else {
+                    -motion[axis]
+                };
+                let mut normal = [0.0, 0.0];
+                normal[axis] = 1.0f32.copysign(side);
+                Some((t_enter.max(0.0), rotate((normal[0], normal[1]), angle)))
+            },
+        }
+    }
+
+    /// A point of the arena in the frame of a wall, centered on it and
+    /// aligned with its sides.
+    fn local_point(&self, x: f32, y: f32) -> (f32, f32) {
+        match *self {
+            Obstacle::Circle { x: center_x, y: center_y, .. } => (x - center_x, y - center_y),
+            Obstacle::Wall { x: center_x, y: center_y, angle, .. } => {
+                rotate((x - center_x, y - center_y), -angle)
+            },
+        }
+    }
+
+    /// The opposite of `local_point`.
+    fn world_point(&self, x: f32, y: f32) -> (f32, f32) {
+        match *self {
+            Obstacle::Circle { x: center_x, y: center_y, .. } => (x + center_x, y + center_y),
+            Obstacle::Wall { x: center_x, y: center_y, angle, .. } => {
+                let (x, y) = rotate((x, y), angle);
+                (x + center_x, y + center_y)
+            },
+        }
+    }
+}
+
+/// The simulation is driven purely by a tick counter and a seeded RNG, so the
+/// same seed and the same sequence of commands always produce the same states.
+pub struct Game {
+    config: GameConfig,
+    pub state: GameState,
+    rng: StdRng,
+    current_tick: u64,
+    bullet_id_counter: u32,
+    item_id_counter: u32,
+    // Player ID -> tick at which the next survival point is awarded
+    survival_times: HashMap<u32, u64>,
+    stats: HashMap<u32, PlayerStats>,
+    // Player ID -> score, of the players who left during the match
+    left_players: HashMap<u32, u32>,
+    last_item_spawn_tick: u64,
+    // Scratch space for the collision detection, kept around between ticks
+    bullet_grid: SpatialGrid,
+    player_grid: SpatialGrid,
+    candidates: Vec<usize>,
+}
+
+impl Game {
+    /// Creates a new game seeded from `config.seed`, or from a random seed if
+    /// the config doesn't specify one.
+    pub fn new(config: GameConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        info!("Game seeded with {}", seed);
+
+        let mut state = GameState::new((config.bound_x, config.bound_y), config.rules.tick_rate);
+        state.obstacles = config.obstacles.clone();
+        state.rules = config.rules;
+
+        Self {
+            state,
+            rng: StdRng::seed_from_u64(seed),
+            current_tick: 0,
+            bullet_id_counter: 0,
+            item_id_counter: 0,
+            survival_times: HashMap::new(),
+            stats: HashMap::new(),
+            left_players: HashMap::new(),
+            last_item_spawn_tick: 0,
+            bullet_grid: SpatialGrid::new((config.bound_x, config.bound_y), GRID_CELL_SIZE),
+            player_grid: SpatialGrid::new((config.bound_x, config.bound_y), GRID_CELL_SIZE),
+            candidates: vec![],
+            config: GameConfig { seed: Some(seed), ..config },
+        }
+    }
+
+    pub fn config(&self) -> GameConfig {
+        self.config.clone()
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.config.rules
+    }
+
+    /// Converts a game time to the number of ticks it spans at this game's tick rate.
+    fn ticks(&self, seconds: f32) -> u64 {
+        duration_to_ticks(rule_duration(seconds), self.config.rules.tick_rate)
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    /// The kills, deaths and survival points of each player of the match,
+    /// those who left included.
+    pub fn stats(&self) -> &HashMap<u32, PlayerStats> {
+        &self.stats
+    }
+
+    /// The score each player who left the match had when they left.
+    pub fn left_players(&self) -> &HashMap<u32, u32> {
+        &self.left_players
+    }
+
+    /// Starts the game over with the same players. The tick counter keeps
+    /// going, so clients never see it go backwards.
+    pub fn reset(&mut self) {
+        let mut new = Game::new(self.config.clone());
+        new.current_tick = self.current_tick;
+        new.last_item_spawn_tick = self.current_tick;
+        new.state.tick = self.current_tick;
+        for player in self.state.players.iter() {
+            new.add_player(player.id);
+        }
+        for corpse in self.state.dead.iter() {
+            new.add_player(corpse.player.id);
+        }
+        new.state.teams = std::mem::take(&mut self.state.teams);
+        new.update_team_scores();
+        let _ = std::mem::replace(self, new);
+    }
+
+    fn bounds(&self) -> (f32, f32) {
+        (self.config.bound_x, self.config.bound_y)
+    }
+
+    fn build_player_grid(&mut self) {
+        self.player_grid.clear();
+        for (index, player) in self.state.players.iter().enumerate() {
+            self.player_grid.insert(index, player.x, player.y, player.radius);
+        }
+    }
+
+    pub fn add_player(&mut self, player_id: u32) {
+        let rules = self.config.rules;
+        let mut player = PlayerState::new(player_id, &rules);
+        let bounds = self.bounds();
+        player.randomize(&mut self.rng, bounds, &rules, &self.config.obstacles);
+        self.state.players.push(player);
+        self.survival_times
+            .insert(player_id, self.current_tick + self.ticks(rules.survival_timeout_seconds));
+        self.stats.insert(player_id, PlayerStats::default());
+        self.left_players.remove(&player_id);
+    }
+
+    pub fn player_left(&mut self, player_id: u32) {
+        info!("Player {} left!", player_id);
+
+        if let Some(idx) = self.state.players.iter().position(|p| p.id == player_id) {
+            self.state.players.remove(idx);
+        }
+        if let Some(idx) = self.state.dead.iter().position(|p| p.player.id == player_id) {
+            self.state.dead.remove(idx);
+        }
+
+        // Their stats stay around for the results of the match
+        let score = self.state.scoreboard.remove(&player_id).unwrap_or_default();
+        self.left_players.insert(player_id, score);
+        self.survival_times.remove(&player_id);
+        for team in &mut self.state.teams {
+            team.players.retain(|&id| id != player_id);
+        }
+        self.update_team_scores();
+    }
+
+    /// Turns the game into a team game, with a team for each name. Their IDs
+    /// follow the order of the names, starting from 0.
+    pub fn set_teams(&mut self, names: Vec<String>) {
+        self.state.teams = names
+            .into_iter()
+            .enumerate()
+            .map(|(id, name)| Team { id: id as u32, name, players: vec![], score: 0 })
+            .collect();
+    }
+
+    /// Moves a player to a team, taking their score with them.
+    pub fn join_team(&mut self, player_id: u32, team_id: u32) {
+        for team in &mut self.state.teams {
+            team.players.retain(|&id| id != player_id);
+            if team.id == team_id {
+                team.players.push(player_id);
+            }
+        }
+        self.update_team_scores();
+    }
+
+    fn update_team_scores(&mut self) {
+        let scoreboard = &self.state.scoreboard;
+        for team in &mut self.state.teams {
+            team.score = team.players.iter().filter_map(|id| scoreboard.get(id)).sum();
+        }
+    }
+
+    /// Applies a command of a player, or tells why it had no effect.
+    pub fn handle_cmd(&mut self, player_id: u32, cmd: GameCommand) -> Result<(), CommandError> {
+        // info!("Player {} sent command {:#?}", player_id, cmd);
+
+        if let Some(player) = self.state.players.iter_mut().find(|p| p.id == player_id) {
+            match cmd {
+                GameCommand::Rotate(angle) => {
+                    player.angle = angle;
+                },
+                GameCommand::Throttle(throttle) => {
+                    // Bound and re-map throttle inputs, NaN included.
+                    let throttle = if throttle.is_nan() { 0.0 } else { throttle.clamp(0.0, 1.0) };
+
+                    player.throttle = throttle;
+                },
+                GameCommand::Fire => {
+                    let active_bullets = self
+                        .state
+                        .bullets
+                        .iter()
+                        .filter(|bullet| bullet.player_id == player.id)
+                        .count();
+
+                    if active_bullets < player.bullet_limit as usize {
+                        let bullet_id = self.bullet_id_counter;
+                        self.bullet_id_counter = self.bullet_id_counter.wrapping_add(1);
+
+                        let distance_from_player: f32 = 5.0;
+                        let (bullet_x, bullet_y) = angle_to_vector(player.angle);
+
+                        self.state.bullets.push(BulletState {
+                            id: bullet_id,
+                            player_id: player.id,
+                            angle: player.angle,
+                            x: player.x + (bullet_x * distance_from_player),
+                            y: player.y + (bullet_y * distance_from_player),
+                            radius: player.bullet_radius,
+                            speed: player.bullet_speed,
+                        });
+                    } else {
+                        return Err(CommandError::BulletLimit);
+                    }
+                },
+                // The lobby is up to the server
+                GameCommand::Ready => {},
+            }
+
+            Ok(())
+        } else if self.state.dead.iter().any(|corpse| corpse.player.id == player_id) {
+            Err(CommandError::Dead)
+        } else {
+            Err(CommandError::NotInGame)
+        }
+    }
+
+    pub fn init(&mut self) {}
+
+    /// Advances the simulation by a single tick of `1 / tick_rate` seconds.
+    pub fn tick(&mut self) {
+        let rules = self.config.rules;
+        let dt = 1.0 / rules.tick_rate;
+        self.current_tick += 1;
+        self.state.tick = self.current_tick;
+        let now = self.current_tick;
+        let respawn_tick = now + self.ticks(rules.respawn_delay_seconds);
+
+        let bounds = self.bounds();
+        // Revive the dead
+        let revived = self
+            .state
+            .dead
+            .extract_if(.., |corpse| corpse.respawn_tick <= now)
+            .map(|dead| dead.player)
+            .inspect(|player| info!("revived player {}", player.id));
+
+        self.state.players.extend(revived);
+
+        if now - self.last_item_spawn_tick > self.ticks(rules.item_spawn_interval_seconds)
+            && self.state.items.len() < rules.max_items as usize
+        {
+            let item_id = self.item_id_counter;
+            self.item_id_counter = self.item_id_counter.wrapping_add(1);
+            let item = Item::new_randomized(item_id, &mut self.rng, bounds, &self.config.obstacles);
+            self.state.items.push(item);
+
+            self.last_item_spawn_tick = now;
+        }
+
+        // Advance bullets
+        for bullet in &mut self.state.bullets {
+            advance_bullet(bullet, dt);
+        }
+
+        // Obstacles destroy the bullets that run into them, or send them back
+        let mut paths = Vec::with_capacity(self.state.bullets.len());
+        let mut destroyed_bullets = vec![false; self.state.bullets.len()];
+        for (index, bullet) in self.state.bullets.iter_mut().enumerate() {
+            match bounce_bullet(bullet, dt, &self.config.obstacles) {
+                Some(path) => paths.push(path),
+                None => destroyed_bullets[index] = true,
+            }
+        }
+        retain_by_index(&mut self.state.bullets, |index| !destroyed_bullets[index]);
+
+        for player in &mut self.state.players {
+            // Move the player
+            let (vel_x, vel_y) = angle_to_vector(player.angle);
+
+            let speed = player.speed(&rules);
+            player.x += vel_x * speed * player.throttle * dt;
+            player.y += vel_y * speed * player.throttle * dt;
+
+            // Keep the players out of the obstacles, and in bounds
+            for obstacle in &self.config.obstacles {
+                if let Some((x, y)) = obstacle.push_out(player.x, player.y, player.radius) {
+                    player.x = x;
+                    player.y = y;
+                }
+            }
+            player.x = player.x.max(player.radius).min(self.config.bound_x - player.radius);
+            player.y = player.y.max(player.radius).min(self.config.bound_y - player.radius);
+        }
+
+        let bounds = self.bounds();
+        let bound_x = bounds.0;
+        let bound_y = bounds.1;
+
+        // Bullets destroy each other, depending on the paths they travelled during the tick
+        self.bullet_grid.clear();
+        for (index, bullet) in self.state.bullets.iter().enumerate() {
+            let (x, y, radius) = paths[index].bounds(bullet.radius);
+            self.bullet_grid.insert(index, x, y, radius);
+        }
+
+        let mut destroyed_bullets = vec![false; self.state.bullets.len()];
+        for (index, bullet) in self.state.bullets.iter().enumerate() {
+            let (x, y, radius) = paths[index].bounds(bullet.radius);
+            self.bullet_grid.query(x, y, radius, &mut self.candidates);
+
+            for &other_index in self.candidates.iter().filter(|&&other| other > index) {
+                let other = &self.state.bullets[other_index];
+
+                if paths[index].is_colliding(&paths[other_index], bullet.radius + other.radius) {
+                    destroyed_bullets[index] = true;
+                    destroyed_bullets[other_index] = true;
+                }
+            }
+        }
+        retain_by_index(&mut self.state.bullets, |index| !destroyed_bullets[index]);
+        retain_by_index(&mut paths, |index| !destroyed_bullets[index]);
+
+        // count collisions
+        self.build_player_grid();
+        let mut colliding_players = vec![false; self.state.players.len()];
+        for (index, player) in self.state.players.iter().enumerate() {
+            self.player_grid.query(player.x, player.y, player.radius, &mut self.candidates);
+
+            for &other_index in self.candidates.iter().filter(|&&other| other > index) {
+                if player.is_colliding(&self.state.players[other_index]) {
+                    colliding_players[index] = true;
+                    colliding_players[other_index] = true;
+                }
+            }
+        }
+
+        let players = std::mem::take(&mut self.state.players);
+        for (index, mut player) in players.into_iter().enumerate() {
+            if colliding_players[index] {
+                player.randomize(&mut self.rng, bounds, &rules, &self.config.obstacles);
+                self.state.dead.push(DeadPlayer { respawn_tick, player });
+            } else {
+                self.state.players.push(player);
+            }
+        }
+
+        // count the dead
+        let mut hits = vec![];
+        let mut used_bullets = vec![false; self.state.bullets.len()];
+        let mut deceased = vec![];
+        let bounds = self.bounds();
+
+        let team_of: HashMap<u32, u32> = self
+            .state
+            .teams
+            .iter()
+            .flat_map(|team| team.players.iter().map(move |&player_id| (player_id, team.id)))
+            .collect();
+
+        self.build_player_grid();
+        let mut is_dead = vec![false; self.state.players.len()];
+        for (bullet_index, bullet) in self.state.bullets.iter().enumerate() {
+            let path = &paths[bullet_index];
+            let (x, y, radius) = path.bounds(bullet.radius);
+            self.player_grid.query(x, y, radius, &mut self.candidates);
+
+            for &player_index in &self.candidates {
+                let player = &self.state.players[player_index];
+                let player_path = BulletPath::at_rest(player.x, player.y);
+
+                if !is_dead[player_index]
+                    && path.is_colliding(&player_path, bullet.radius + player.radius)
+                    && bullet.player_id != player.id
+                {
+                    let friendly = team_of
+                        .get(&bullet.player_id)
+                        .is_some_and(|team| team_of.get(&player.id) == Some(team));
+                    if friendly && rules.friendly_fire == FriendlyFire::Ignore {
+                        continue;
+                    }
+
+                    info!(
+                        "Player {} killed player {} at ({}, {})",
+                        bullet.player_id, player.id, bullet.x, bullet.y
+                    );
+                    hits.push((bullet.player_id, friendly));
+                    used_bullets[bullet_index] = true;
+                    is_dead[player_index] = true;
+                    deceased.push(player_index);
+                }
+            }
+        }
+
+        let survival_timeout = self.ticks(rules.survival_timeout_seconds);
+        let mut players: Vec<Option<PlayerState>> =
+            std::mem::take(&mut self.state.players).into_iter().map(Some).collect();
+        for player_index in deceased {
+            if let Some(mut player) = players[player_index].take() {
+                // Reset their survival time bonus
+                self.survival_times.insert(player.id, now + survival_timeout);
+                self.stats.entry(player.id).or_default().deaths += 1;
+
+                player.randomize(&mut self.rng, bounds, &rules, &self.config.obstacles);
+                self.state.dead.push(DeadPlayer { respawn_tick, player });
+            }
+        }
+        self.state.players = players.into_iter().flatten().collect();
+
+        self.build_player_grid();
+        let mut used_items = vec![false; self.state.items.len()];
+        for (item_index, item) in self.state.items.iter().enumerate() {
+            self.player_grid.query(item.x, item.y, item.radius, &mut self.candidates);
+
+            for &player_index in &self.candidates {
+                let player = &mut self.state.players[player_index];
+
+                if player.is_colliding(item) {
+                    item.apply_to(player);
+                    used_items[item_index] = true;
+                }
+            }
+        }
+        retain_by_index(&mut self.state.items, |index| !used_items[index]);
+
+        // Clear out used bullets
+        retain_by_index(&mut self.state.bullets, |index| !used_bullets[index]);
+
+        // Remove out-of-bound bullets, once they had a chance to hit something on their way out
+        self.state.bullets.retain(|b| {
+            b.x > (b.radius)
+                && b.x < (bound_x + b.radius)
+                && b.y > (b.radius)
+                && b.y < (bound_y + b.radius)
+        });
+
+        // Update the scoreboard, killing a teammate is worth nothing at best
+        for (player_id, friendly) in hits {
+            if !friendly {
+                *self.state.scoreboard.entry(player_id).or_default() += 1;
+                self.stats.entry(player_id).or_default().kills += 1;
+            } else if rules.friendly_fire == FriendlyFire::Penalize {
+                let score = self.state.scoreboard.entry(player_id).or_default();
+                *score = score.saturating_sub(1);
+            }
+        }
+
+        // Reward players for staying alive
+        let survival_point_interval = self.ticks(rules.survival_point_interval_seconds);
+        for (player_id, next_reward_tick) in &mut self.survival_times {
+            if *next_reward_tick <= now {
+                // Only reward if there is more than 1 player in the game
+                if self.state.players.len() > 1 {
+                    let score = self.state.scoreboard.entry(*player_id).or_default();
+                    *score = score.saturating_add(rules.survival_points);
+                    let stats = self.stats.entry(*player_id).or_default();
+                    stats.survival_points =
+                        stats.survival_points.saturating_add(rules.survival_points);
+                }
+
+                *next_reward_tick = now + survival_point_interval;
+            }
+        }
+
+        self.update_team_scores();
+    }
+}
+
+// TODO(jake): rewrite tests.... maybe
+
+fn angle_to_vector(angle: f32) -> (f32, f32) {
+    (angle.cos(), angle.sin())
+}
+
+/// Turns a vector by `angle` radians.
+fn rotate((x, y): (f32, f32), angle: f32) -> (f32, f32) {
+    let (sin, cos) = angle.sin_cos();
+    (x * cos - y * sin, x * sin + y * cos)
+}
+
+/// Moves a bullet by the distance it travels in a tick of `dt` seconds. Bullets
+/// only ever fly in a straight line, so clients receiving deltas predict them
+/// with this too.
+pub(crate) fn advance_bullet(bullet: &mut BulletState, dt: f32) {
+    let (motion_x, motion_y) = bullet_motion(bullet, dt);
+
+    bullet.x += motion_x;
+    bullet.y += motion_y;
+}
+
+/// Checks the path a bullet travelled during the last `dt` seconds against
+/// the obstacles. Returns None when one of them destroys it. A bullet hitting
+/// a reflective obstacle bounces off it, at most once a tick, and travels the
+/// rest of the way in its new direction.
+fn bounce_bullet(bullet: &mut BulletState, dt: f32, obstacles: &[Obstacle]) -> Option<BulletPath> {
+    let (motion_x, motion_y) = bullet_motion(bullet, dt);
+    let start = (bullet.x - motion_x, bullet.y - motion_y);
+
+    let hit = obstacles
+        .iter()
+        .filter_map(|obstacle| {
+            let (t, normal) = obstacle.sweep(start, (motion_x, motion_y), bullet.radius)?;
+            Some((t, normal, obstacle))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    let (t, (normal_x, normal_y), obstacle) = match hit {
+        Some(hit) => hit,
+        None => return Some(BulletPath { start, bounce: None, end: (bullet.x, bullet.y) }),
+    };
+    if !obstacle.is_reflective() {
+        return None;
+    }
+
+    let dot = motion_x * normal_x + motion_y * normal_y;
+    let (reflected_x, reflected_y) =
+        (motion_x - 2.0 * dot * normal_x, motion_y - 2.0 * dot * normal_y);
+    let bounce = (start.0 + motion_x * t, start.1 + motion_y * t);
+    bullet.x = bounce.0 + reflected_x * (1.0 - t);
+    bullet.y = bounce.1 + reflected_y * (1.0 - t);
+    bullet.angle = reflected_y.atan2(reflected_x);
+    Some(BulletPath { start, bounce: Some((t, bounce)), end: (bullet.x, bullet.y) })
+}
+
+/// Where a bullet went during a tick: in a straight line from `start` to
+/// `end`, unless it bounced off an obstacle on the way, at the given fraction
+/// of the tick and point.
+#[derive(Clone, Copy, Debug)]
+struct BulletPath {
+    start: (f32, f32),
+    bounce: Option<(f32, (f32, f32))>,
+    end: (f32, f32),
+}
+
+impl BulletPath {
+    /// The path of something that stayed put, like players as far as
+    /// bullets are concerned.
+    fn at_rest(x: f32, y: f32) -> Self {
+        Self { start: (x, y), bounce: None, end: (x, y) }
+    }
+
+    /// Where the bullet was at the given fraction of the tick.
+    fn position(&self, t: f32) -> (f32, f32) {
+        let lerp = |(from_x, from_y): (f32, f32), (to_x, to_y): (f32, f32), t: f32| {
+            (from_x + (to_x - from_x) * t, from_y + (to_y - from_y) * t)
+        };
+
+        match self.bounce {
+            Some((bounce_t, point)) if t <= bounce_t => {
+                lerp(self.start, point, if bounce_t > 0.0 { t / bounce_t } else { 1.0 })
+            },
+            Some((bounce_t, point)) => lerp(point, self.end, (t - bounce_t) / (1.0 - bounce_t)),
+            None => lerp(self.start, self.end, t),
+        }
+    }
+
+    /// Whether two paths came closer than `radii` at any time during the tick.
+    /// Both go in a straight line between their bounces.
+    fn is_colliding(&self, other: &BulletPath, radii: f32) -> bool {
+        let bounce_t = |path: &BulletPath| path.bounce.map_or(0.0, |(t, _)| t);
+        let mut times = [0.0, bounce_t(self), bounce_t(other), 1.0];
+        times.sort_by(f32::total_cmp);
+
+        times.windows(2).any(|window| {
+            let (from, to) = (self.position(window[0]), self.position(window[1]));
+            let (other_from, other_to) = (other.position(window[0]), other.position(window[1]));
+            sweep_circles(
+                from,
+                (to.0 - from.0, to.1 - from.1),
+                other_from,
+                (other_to.0 - other_from.0, other_to.1 - other_from.1),
+                radii,
+            )
+        })
+    }
+
+    /// A circle enclosing the path, for a bullet of the given radius.
+    fn bounds(&self, radius: f32) -> (f32, f32, f32) {
+        let points = [self.start, self.bounce.map_or(self.end, |(_, point)| point), self.end];
+        let min_x = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+        let max_x = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+        let (half_width, half_height) = ((max_x - min_x) / 2.0, (max_y - min_y) / 2.0);
+
+        (
+            min_x + half_width,
+            min_y + half_height,
+            radius + (half_width * half_width + half_height * half_height).sqrt(),
+        )
+    }
+}
+
+/// The distance a bullet travels in `dt` seconds.
+fn bullet_motion(bullet: &BulletState, dt: f32) -> (f32, f32) {
+    let (vel_x, vel_y) = angle_to_vector(bullet.angle);
+    (vel_x * bullet.speed * dt, vel_y * bullet.speed * dt)
+}
+
+/// Keeps the items for which `keep` returns true when given their index.
+fn retain_by_index<T>(items: &mut Vec<T>, mut keep: impl FnMut(usize) -> bool) {
+    let mut index = 0;
+    items.retain(|_| {
+        index += 1;
+        keep(index - 1)
+    });
+}
+
+/// Converts a time given in seconds by the rules to a `Duration`. The rules are
+/// validated up front, but a time that doesn't fit, say from an old recording,
+/// counts as no time at all rather than taking the game down.
+pub fn rule_duration(seconds: f32) -> Duration {
+    Duration::try_from_secs_f32(seconds).unwrap_or_else(|err| {
+        warn!("Invalid time of {} seconds in the rules: {}", seconds, err);
+        Duration::ZERO
+    })
+}
+
+/// Converts a wall-clock duration to the number of game ticks it spans.
+pub fn duration_to_ticks(duration: Duration, tick_rate: f32) -> u64 {
+    (duration.as_secs_f32() * tick_rate).round() as u64
+}
+
+/// The game time it takes to play the given number of ticks.
+pub fn ticks_to_duration(ticks: u64, tick_rate: f32) -> Duration {
+    Duration::from_secs_f64(ticks as f64 / f64::from(tick_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MatchResults;
+
+    fn bullet(x: f32, y: f32, angle: f32, speed: f32) -> BulletState {
+        BulletState { id: 0, player_id: 1, angle, x, y, radius: BULLET_BASE_RADIUS, speed }
+    }
+
+    fn player(id: u32, x: f32, y: f32) -> PlayerState {
+        PlayerState { x, y, throttle: 0.0, ..PlayerState::new(id, &GameRules::default()) }
+    }
+
+    fn path(start: (f32, f32), end: (f32, f32)) -> BulletPath {
+        BulletPath { start, bounce: None, end }
+    }
+
+    #[test]
+    fn swept_collision_catches_bullets_crossing_a_player() {
+        let target = player(2, 100.0, 100.0);
+        let at_rest = BulletPath::at_rest(target.x, target.y);
+        let radii = target.radius + BULLET_BASE_RADIUS;
+
+        // Went from x = 50 to x = 150 in one tick, right through the player
+        let fast = bullet(150.0, 100.0, 0.0, 100.0);
+        assert!(!fast.is_colliding(&target));
+        assert!(path((50.0, 100.0), (150.0, 100.0)).is_colliding(&at_rest, radii));
+
+        // Same path, a little too far from the player to touch them
+        let wide = 100.0 + radii + 1.0;
+        assert!(!path((50.0, wide), (150.0, wide)).is_colliding(&at_rest, radii));
+
+        // Still short of the player
+        assert!(!path((-20.0, 100.0), (80.0, 100.0)).is_colliding(&at_rest, radii));
+    }
+
+    #[test]
+    fn swept_collision_catches_bullets_passing_each_other() {
+        // Head-on, they swapped sides during the tick
+        let left = bullet(110.0, 100.0, 0.0, 100.0);
+        let right = bullet(90.0, 100.0, std::f32::consts::PI, 100.0);
+        assert!(!left.is_colliding(&right));
+        let left_path = path((10.0, 100.0), (110.0, 100.0));
+        let right_path = path((190.0, 100.0), (90.0, 100.0));
+        assert!(left_path.is_colliding(&right_path, left.radius + right.radius));
+    }
+
+    #[test]
+    fn fast_bullets_dont_tunnel_through_players() {
+        let mut game = Game::new(GameConfig {
+            bound_x: 1000.0,
+            bound_y: 1000.0,
+            seed: Some(0),
+            rules: GameRules { max_items: 0, ..GameRules::default() },
+            obstacles: vec![],
+        });
+        game.add_player(1);
+        game.add_player(2);
+        game.state.players = vec![player(1, 50.0, 500.0), player(2, 300.0, 500.0)];
+
+        // Travels 400 pixels in a tick, from one side of player 2 to the other
+        let speed = 400.0 * game.rules().tick_rate;
+        game.state.bullets = vec![bullet(100.0, 500.0, 0.0, speed)];
+        game.tick();
+
+        assert!(game.state.bullets.is_empty());
+        assert!(game.state.dead.iter().any(|corpse| corpse.player.id == 2));
+        assert_eq!(game.stats()[&1].kills, 1);
+        assert_eq!(game.stats()[&2].deaths, 1);
+    }
+
+    #[test]
+    fn bounced_bullets_hit_what_was_on_their_way() {
+        let mut game = Game::new(GameConfig {
+            bound_x: 1000.0,
+            bound_y: 1000.0,
+            seed: Some(0),
+            rules: GameRules { max_items: 0, ..GameRules::default() },
+            obstacles: vec![Obstacle::Wall {
+                x: 220.0,
+                y: 200.0,
+                width: 40.0,
+                height: 400.0,
+                angle: 0.0,
+                reflective: true,
+            }],
+        });
+        for player_id in 1..=3 {
+            game.add_player(player_id);
+        }
+        // Player 2 is on the way to the wall, player 3 behind it, where the
+        // bullet would have come from had it always flown its new way
+        game.state.players =
+            vec![player(1, 800.0, 800.0), player(2, 130.0, 130.0), player(3, 255.0, 137.0)];
+
+        // Flies from (100, 100) by (120, 120), and bounces off the wall at
+        // (196, 196), 80% of the way through the tick
+        assert_eq!(BULLET_BASE_RADIUS, 4.0);
+        let speed = 120.0 * std::f32::consts::SQRT_2 * game.rules().tick_rate;
+        let angle = std::f32::consts::FRAC_PI_4;
+        game.state.bullets = vec![bullet(100.0, 100.0, angle, speed)];
+        game.tick();
+
+        let dead: Vec<u32> = game.state.dead.iter().map(|corpse| corpse.player.id).collect();
+        assert_eq!(dead, vec![2]);
+        assert!(game.state.bullets.is_empty());
+    }
+
+    #[test]
+    fn players_who_left_stay_in_the_results() {
+        let mut game = Game::new(GameConfig {
+            bound_x: 1000.0,
+            bound_y: 1000.0,
+            seed: Some(0),
+            rules: GameRules::default(),
+            obstacles: vec![],
+        });
+        game.add_player(1);
+        game.add_player(2);
+        game.state.scoreboard.insert(2, 4);
+        game.stats.get_mut(&2).unwrap().kills = 4;
+        game.player_left(2);
+
+        assert!(!game.state.scoreboard.contains_key(&2));
+        let results = MatchResults::new(
+            &game.state.scoreboard,
+            game.stats(),
+            game.left_players(),
+            &HashMap::new(),
+            &game.state.teams,
+        );
+        let left: Vec<(u32, u32, u32, bool)> = results
+            .standings
+            .iter()
+            .map(|standing| {
+                (standing.player_id, standing.score, standing.stats.kills, standing.left)
+            })
+            .collect();
+        assert_eq!(left, vec![(2, 4, 4, true), (1, 0, 0, false)]);
+
+        // Coming back starts them over
+        game.add_player(2);
+        assert!(game.left_players().is_empty());
+    }
+}
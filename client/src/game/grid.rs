@@ -0,0 +1,142 @@
+/// A uniform grid over the arena, used as the broad phase of the collision
+/// detection. Each entity is stored, by its index, in every cell its bounding
+/// box overlaps, so only entities sharing a cell need an exact test.
+#[derive(Debug)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new((bound_x, bound_y): (f32, f32), cell_size: f32) -> Self {
+        let columns = (bound_x / cell_size).ceil().max(1.0) as usize;
+        let rows = (bound_y / cell_size).ceil().max(1.0) as usize;
+
+        Self { cell_size, columns, rows, cells: vec![vec![]; columns * rows] }
+    }
+
+    /// Empties every cell, keeping their allocations around for the next tick.
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, x: f32, y: f32, radius: f32) {
+        let (min_column, max_column, min_row, max_row) = self.cell_range(x, y, radius);
+
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                self.cells[row * self.columns + column].push(index);
+            }
+        }
+    }
+
+    /// Collects the indices of the entities sharing a cell with the given
+    /// circle into `out`, sorted and without duplicates.
+    pub fn query(&self, x: f32, y: f32, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let (min_column, max_column, min_row, max_row) = self.cell_range(x, y, radius);
+
+        for row in min_row..=max_row {
+            for column in min_column..=max_column {
+                out.extend_from_slice(&self.cells[row * self.columns + column]);
+            }
+        }
+
+        out.sort_unstable();
+        out.dedup();
+    }
+
+    // Entities slightly out of the arena are clamped to the border cells.
+    fn cell_range(&self, x: f32, y: f32, radius: f32) -> (usize, usize, usize, usize) {
+        let to_cell = |value: f32, count: usize| {
+            ((value / self.cell_size).floor().max(0.0) as usize).min(count - 1)
+        };
+
+        (
+            to_cell(x - radius, self.columns),
+            to_cell(x + radius, self.columns),
+            to_cell(y - radius, self.rows),
+            to_cell(y + radius, self.rows),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const BOUNDS: (f32, f32) = (300.0, 200.0);
+    const CELL_SIZE: f32 = 50.0;
+
+    /// Random circles, plus some sitting right on cell borders, spanning
+    /// several cells, or slightly out of the arena.
+    fn entities() -> Vec<(f32, f32, f32)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut entities: Vec<(f32, f32, f32)> = (0..200)
+            .map(|_| {
+                (
+                    rng.gen_range(0.0, BOUNDS.0),
+                    rng.gen_range(0.0, BOUNDS.1),
+                    rng.gen_range(0.5, 20.0),
+                )
+            })
+            .collect();
+        entities.extend(&[
+            (50.0, 50.0, 0.0),
+            (50.0, 100.0, 1.0),
+            (49.0, 100.0, 1.0),
+            (100.0, 0.0, 0.5),
+            (150.0, 100.0, 80.0),
+            (10.0, 190.0, 120.0),
+            (-5.0, -5.0, 3.0),
+            (BOUNDS.0 + 5.0, BOUNDS.1, 6.0),
+            (BOUNDS.0, 100.0, 0.0),
+        ]);
+        entities
+    }
+
+    #[test]
+    fn finds_every_overlapping_pair() {
+        let entities = entities();
+        let mut grid = SpatialGrid::new(BOUNDS, CELL_SIZE);
+        for (index, &(x, y, radius)) in entities.iter().enumerate() {
+            grid.insert(index, x, y, radius);
+        }
+
+        let mut candidates = vec![];
+        for (a, &(x, y, radius)) in entities.iter().enumerate() {
+            grid.query(x, y, radius, &mut candidates);
+            assert!(candidates.windows(2).all(|pair| pair[0] < pair[1]));
+
+            let overlapping =
+                entities.iter().enumerate().filter(|&(_, &(other_x, other_y, other_radius))| {
+                    let (dx, dy) = (x - other_x, y - other_y);
+                    (dx * dx + dy * dy).sqrt() <= radius + other_radius
+                });
+            for (b, _) in overlapping {
+                assert!(
+                    candidates.binary_search(&b).is_ok(),
+                    "{:?} misses {:?}",
+                    entities[a],
+                    entities[b]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clear_empties_every_cell() {
+        let mut grid = SpatialGrid::new(BOUNDS, CELL_SIZE);
+        grid.insert(0, 150.0, 100.0, 80.0);
+        grid.clear();
+
+        let mut candidates = vec![1];
+        grid.query(150.0, 100.0, 200.0, &mut candidates);
+        assert!(candidates.is_empty());
+    }
+}
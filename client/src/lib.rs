@@ -8,19 +8,34 @@ extern crate serde_derive;
 
 pub mod analyzer;
 pub mod behavior;
+pub mod delta;
+pub mod game;
 pub mod geom;
 pub mod models;
+pub mod simulation;
+#[cfg(any(target_arch = "wasm32", doc))]
+pub mod wasm;
 
-use crate::models::{ClientState, GameCommand, GameState, ServerToClient, MIN_COMMAND_INTERVAL};
+use crate::models::{ClientState, CommandError, GameCommand};
+#[cfg(feature = "net")]
+use crate::models::{
+    GameState, QueueToClient, ServerToClient, UpdateMode, WireFormat, MIN_COMMAND_INTERVAL,
+};
+#[cfg(feature = "net")]
 use failure::Error;
+#[cfg(feature = "net")]
 use futures::{Future, Sink, Stream};
+#[cfg(feature = "net")]
 use std::{
     env,
     fmt::Debug,
     sync::{Arc, Mutex},
 };
+#[cfg(feature = "net")]
 use tokio_tungstenite as tokio_ws;
+#[cfg(feature = "net")]
 use tokio_ws::tungstenite as ws;
+#[cfg(feature = "net")]
 use url::{
     percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET},
     Url,
@@ -33,8 +48,20 @@ pub trait Handler {
     /// state of the world and do a single action based on its state. It's not
     /// called when the player is dead and waiting to be respawn.
     fn tick(&mut self, state: &ClientState) -> Option<GameCommand>;
+
+    /// Called with the outcome of each command returned by `tick`, as reported
+    /// by the server: either the game tick at which it was applied, or why it
+    /// had no effect. It's also called for the `GameCommand::Ready` sent on
+    /// connection. Does nothing by default.
+    fn on_command_result(&mut self, _result: Result<u64, CommandError>) {}
 }
 
+// The outcomes of commands received from the server, waiting to be handed to
+// the `Handler`
+#[cfg(feature = "net")]
+type CommandResults = Arc<Mutex<Vec<Result<u64, CommandError>>>>;
+
+#[cfg(feature = "net")]
 fn log_err<E: Debug>(e: E) {
     eprintln!("{:?}", e)
 }
@@ -43,20 +70,35 @@ fn is_player_alive(state: &ClientState) -> bool {
     state.game_state.players.iter().find(|player| player.id == state.id).is_some()
 }
 
+#[cfg(feature = "net")]
 fn build_game_loop<H, S, D>(
     sink: S,
     client_state: Arc<Mutex<ClientState>>,
-    mut handler: H,
+    command_results: CommandResults,
+    handler: Arc<Mutex<H>>,
 ) -> impl Future<Item = (), Error = ()>
 where
     H: Handler + Send + 'static,
     S: Sink<SinkItem = ws::Message, SinkError = D>,
     D: Debug,
 {
+    let mut sent_ready = false;
+
     // Create a stream that produces at our desired interval
     tokio::timer::Interval::new_interval(MIN_COMMAND_INTERVAL)
         // Give the user a chance to take a turn
         .filter_map(move |_| {
+            let mut handler = handler.lock().unwrap();
+            for result in command_results.lock().unwrap().drain(..) {
+                handler.on_command_result(result);
+            }
+
+            // Don't keep the lobby waiting, a running bot is a ready bot
+            if !sent_ready {
+                sent_ready = true;
+                return Some(GameCommand::Ready);
+            }
+
             let client_state = &*client_state.lock().unwrap();
             if is_player_alive(client_state) {
                 handler.tick(client_state)
@@ -75,19 +117,32 @@ where
         .map(|_| ()) // throw away leftovers from forward
 }
 
+#[cfg(feature = "net")]
 fn build_state_updater<S, D>(
     stream: S,
     client_state: Arc<Mutex<ClientState>>,
+    command_results: CommandResults,
+    until_game_over: bool,
 ) -> impl Future<Item = (), Error = ()>
 where
     S: Stream<Item = ws::Message, Error = D>,
     D: Debug,
 {
+    // The sequence number of the last keyframe or delta applied
+    let mut last_seq: Option<u64> = None;
+
     stream
-        // We only care about text websocket messages.
-        .filter_map(|message| message.into_text().ok())
-        // We especially only care about proper JSON messages.
-        .filter_map(|message| serde_json::from_str(&message).ok())
+        // We only care about proper JSON text or MessagePack binary messages,
+        // depending on the wire format we asked for.
+        .filter_map(|message| match message {
+            ws::Message::Text(text) => serde_json::from_str(&text).ok(),
+            ws::Message::Binary(bytes) => rmp_serde::from_slice(&bytes).ok(),
+            _ => None,
+        })
+        // Stop at the end of the match, if asked to
+        .take_while(move |message| {
+            Ok(!(until_game_over && matches!(message, ServerToClient::GameOver(_))))
+        })
         // Update the our game state to the most recent reported by the server.
         .for_each(move |server_to_client_msg| {
             match server_to_client_msg {
@@ -97,6 +152,26 @@ where
                 ServerToClient::GameState(state) => {
                     (*client_state).lock().unwrap().game_state = state;
                 },
+                ServerToClient::Keyframe(keyframe) => {
+                    (*client_state).lock().unwrap().game_state = keyframe.state;
+                    last_seq = Some(keyframe.seq);
+                },
+                ServerToClient::Delta(delta) => {
+                    // A delta only makes sense on top of the update right
+                    // before it, otherwise wait for the next keyframe.
+                    if last_seq.map(|seq| seq + 1) == Some(delta.seq) {
+                        last_seq = Some(delta.seq);
+                        (*client_state).lock().unwrap().game_state.apply_delta(delta);
+                    } else {
+                        last_seq = None;
+                    }
+                },
+                ServerToClient::Ack(tick) => {
+                    command_results.lock().unwrap().push(Ok(tick));
+                },
+                ServerToClient::Error(err) => {
+                    command_results.lock().unwrap().push(Err(err));
+                },
                 _ => {},
             }
 
@@ -107,28 +182,112 @@ where
 
 /// Begin the client-side game loop, using the provided struct that implements `Handler`
 /// to act on behalf of the player.
+///
+/// The player joins the room whose token is in the `ROOM_TOKEN` environment
+/// variable. With `QUEUE` set instead, it waits in the matchmaking queue of
+/// the server, plays the match it's put in, and queues again, forever.
+///
+/// The game state is received as deltas encoded in JSON, unless the
+/// `WIRE_FORMAT` environment variable asks for another `WireFormat` (e.g.
+/// `WIRE_FORMAT=msgpack`). Either way, the `Handler` always sees the full state.
+#[cfg(feature = "net")]
 pub fn run<H>(key: &str, name: &str, handler: H) -> Result<(), Error>
 where
     H: Handler + Send + 'static,
 {
     let host = env::var("SERVER_HOST").unwrap_or("192.168.0.199".into());
+    let format = match env::var("WIRE_FORMAT") {
+        Ok(format) => format.parse().map_err(failure::err_msg)?,
+        Err(_) => WireFormat::default(),
+    };
+    let handler = Arc::new(Mutex::new(handler));
+
+    if env::var_os("QUEUE").is_some() {
+        loop {
+            let room_token = wait_in_queue(&host, key, name)?;
+            play(&host, &room_token, key, name, format, handler.clone(), true)?;
+        }
+    }
+
+    let room_token = env::var("ROOM_TOKEN")
+        .map_err(|_| failure::err_msg("Set ROOM_TOKEN, or QUEUE to join via the queue"))?;
+    play(&host, &room_token, key, name, format, handler, false)
+}
+
+/// Waits in the matchmaking queue until the server puts the player in a
+/// match, returning the token of its room.
+#[cfg(feature = "net")]
+fn wait_in_queue(host: &str, key: &str, name: &str) -> Result<String, Error> {
+    let url = Url::parse(&format!(
+        "wss://{}/queue?key={}&name={}",
+        host,
+        key,
+        utf8_percent_encode(name, DEFAULT_ENCODE_SET),
+    ))?;
+
+    let matched = tokio_ws::connect_async(url)
+        .and_then(|(websocket, _)| {
+            websocket
+                .filter_map(|message| match message {
+                    ws::Message::Text(text) => serde_json::from_str(&text).ok(),
+                    _ => None,
+                })
+                .filter_map(|queue_to_client_msg| match queue_to_client_msg {
+                    QueueToClient::Waiting(waiting) => {
+                        eprintln!("Waiting in the queue with {} bots", waiting);
+                        None
+                    },
+                    QueueToClient::Matched(room_token) => Some(room_token),
+                })
+                .into_future()
+                .map_err(|(err, _)| err)
+        })
+        .map_err(Error::from)
+        .and_then(|(room_token, _)| {
+            room_token.ok_or_else(|| failure::err_msg("The queue closed before a match was found"))
+        });
+
+    tokio::runtime::Runtime::new()?.block_on(matched)
+}
+
+/// Plays in a room until the connection closes, or until the end of the
+/// match if `until_game_over` is set.
+#[cfg(feature = "net")]
+fn play<H>(
+    host: &str,
+    room_token: &str,
+    key: &str,
+    name: &str,
+    format: WireFormat,
+    handler: Arc<Mutex<H>>,
+    until_game_over: bool,
+) -> Result<(), Error>
+where
+    H: Handler + Send + 'static,
+{
     let url = Url::parse(&format!(
-        "wss://{}/socket?key={}&name={}",
+        "wss://{}/socket?room_token={}&key={}&name={}&format={}&updates={}",
         host,
+        room_token,
         key,
-        utf8_percent_encode(name, DEFAULT_ENCODE_SET).to_string()
+        utf8_percent_encode(name, DEFAULT_ENCODE_SET).to_string(),
+        format.as_str(),
+        UpdateMode::Delta.as_str()
     ))?;
 
     let client_state =
         Arc::new(Mutex::new(ClientState { id: 0, game_state: GameState::default() }));
+    let command_results = CommandResults::default();
 
     let client = tokio_ws::connect_async(url)
         .and_then(move |(websocket, _)| {
             // Allow us to build two futures out of this connection - one for send, one for recv.
             let (sink, stream) = websocket.split();
 
-            let game_loop = build_game_loop(sink, client_state.clone(), handler);
-            let state_updater = build_state_updater(stream, client_state);
+            let game_loop =
+                build_game_loop(sink, client_state.clone(), command_results.clone(), handler);
+            let state_updater =
+                build_state_updater(stream, client_state, command_results, until_game_over);
 
             // Return a future that will finish when either one of the two futures finish.
             state_updater.select(game_loop).then(|_| Ok(()))
@@ -1,8 +1,6 @@
+#[cfg(feature = "net")]
 use actix::Message;
-use std::{
-    collections::HashMap,
-    time::{Duration, SystemTime},
-};
+use std::{cmp::Reverse, collections::HashMap, time::Duration};
 
 pub const BULLET_BASE_LIMIT: u32 = 3;
 pub const BULLET_BASE_RADIUS: f32 = 4.0;
@@ -18,16 +16,175 @@ pub const PLAYER_BASE_SPEED: f32 = 300.0;
 pub const PLAYER_MIN_THROTTLE: f32 = -1.0;
 pub const PLAYER_MAX_THROTTLE: f32 = 1.0;
 
+pub const MAX_TICK_RATE: f32 = 240.0;
+
+// The most the other rules can be set to, to keep the game playable
+pub const MAX_RULE_SECONDS: f32 = 3600.0;
+pub const MAX_RULE_SIZE: f32 = 1000.0; // in pixels
+pub const MAX_RULE_SPEED: f32 = 10_000.0; // in pixels-per-second
+pub const MAX_RULE_ITEMS: u32 = 100;
+pub const MAX_RULE_BULLETS: u32 = 100;
+pub const MAX_RULE_POINTS: u32 = 1000;
+
+// Random positions tried for a player or an item to spawn clear of the obstacles
+const SPAWN_ATTEMPTS: u32 = 100;
+
 // Send commands more frequently than this interval, and consequences.
 pub const MIN_COMMAND_INTERVAL: Duration = Duration::from_millis(50);
 
-#[derive(Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GameConfig {
     pub bound_x: f32,
     pub bound_y: f32,
+    /// Seed for the game's RNG. The same seed and the same commands always
+    /// replay the same match. A random seed is picked if it's not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub rules: GameRules,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The rules of a game. Any field left out of a config falls back to the
+/// default rules, so only the ones that differ need to be given.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct GameRules {
+    pub tick_rate: f32, // Game ticks per second
+    pub respawn_delay_seconds: f32,
+    pub item_spawn_interval_seconds: f32,
+    pub max_items: u32, // Max number of items lying in the arena at the same time
+    pub survival_timeout_seconds: f32, // Time alive until you start earning survival points
+    pub survival_point_interval_seconds: f32,
+    pub survival_points: u32, // Points earned at each survival interval
+    pub player_radius: f32,
+    pub player_speed: f32, // in pixels-per-second
+    pub bullet_radius: f32,
+    pub bullet_speed: f32,            // in pixels-per-second
+    pub bullet_limit: u32,            // Max number of bullets of a player flying at the same time
+    pub countdown_seconds: f32, // Time between everybody being ready and the start of the match
+    pub reconnect_grace_seconds: f32, // Time a disconnected player keeps their place in the game
+    pub friendly_fire: FriendlyFire, // What bullets do to teammates, in team games
+}
+
+/// What a bullet does to the teammates of the player who fired it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FriendlyFire {
+    #[default]
+    Ignore, // It flies through them
+    Kill,     // They die, and the shooter doesn't score
+    Penalize, // They die, and the shooter loses a point
+}
+
+impl GameRules {
+    /// Checks that the rules make for a playable game.
+    pub fn validate(&self) -> Result<(), String> {
+        // Each rule along with the most it can be
+        let positive = [
+            ("tick_rate", self.tick_rate, MAX_TICK_RATE),
+            ("player_radius", self.player_radius, MAX_RULE_SIZE),
+            ("bullet_radius", self.bullet_radius, MAX_RULE_SIZE),
+            (
+                "survival_point_interval_seconds",
+                self.survival_point_interval_seconds,
+                MAX_RULE_SECONDS,
+            ),
+        ];
+        let non_negative = [
+            ("respawn_delay_seconds", self.respawn_delay_seconds, MAX_RULE_SECONDS),
+            ("item_spawn_interval_seconds", self.item_spawn_interval_seconds, MAX_RULE_SECONDS),
+            ("survival_timeout_seconds", self.survival_timeout_seconds, MAX_RULE_SECONDS),
+            ("player_speed", self.player_speed, MAX_RULE_SPEED),
+            ("bullet_speed", self.bullet_speed, MAX_RULE_SPEED),
+            ("countdown_seconds", self.countdown_seconds, MAX_RULE_SECONDS),
+            ("reconnect_grace_seconds", self.reconnect_grace_seconds, MAX_RULE_SECONDS),
+        ];
+
+        if let Some((name, ..)) =
+            positive.iter().find(|(_, value, _)| value.is_nan() || *value <= 0.0)
+        {
+            return Err(format!("{} must be positive", name));
+        }
+        if let Some((name, ..)) =
+            non_negative.iter().find(|(_, value, _)| value.is_nan() || *value < 0.0)
+        {
+            return Err(format!("{} must not be negative", name));
+        }
+        // Infinity included
+        if let Some((name, _, max)) =
+            positive.iter().chain(&non_negative).find(|(_, value, max)| value > max)
+        {
+            return Err(format!("{} must be at most {}", name, max));
+        }
+
+        let counts = [
+            ("max_items", self.max_items, MAX_RULE_ITEMS),
+            ("bullet_limit", self.bullet_limit, MAX_RULE_BULLETS),
+            ("survival_points", self.survival_points, MAX_RULE_POINTS),
+        ];
+        if let Some((name, _, max)) = counts.iter().find(|(_, value, max)| value > max) {
+            return Err(format!("{} must be at most {}", name, max));
+        }
+
+        Ok(())
+    }
+}
+
+/// A static obstacle of the arena. Players can't move through it, and bullets
+/// are destroyed by it, or bounce off it when it's `reflective`.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Obstacle {
+    Circle {
+        x: f32,
+        y: f32,
+        radius: f32,
+        #[serde(default)]
+        reflective: bool,
+    },
+    // A rectangle centered on (x, y), turned by `angle` radians
+    Wall {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default)]
+        reflective: bool,
+    },
+}
+
+impl Obstacle {
+    /// Checks that the obstacle has a size, and a finite position.
+    pub fn validate(&self) -> Result<(), String> {
+        let (position, sizes) = match *self {
+            Obstacle::Circle { x, y, radius, .. } => ([x, y, 0.0], vec![radius]),
+            Obstacle::Wall { x, y, width, height, angle, .. } => {
+                ([x, y, angle], vec![width, height])
+            },
+        };
+
+        if position.iter().any(|value| !value.is_finite()) {
+            return Err("The position of an obstacle must be finite".to_string());
+        }
+        if sizes.iter().any(|value| !value.is_finite() || *value <= 0.0) {
+            return Err("The size of an obstacle must be positive".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn is_reflective(&self) -> bool {
+        match *self {
+            Obstacle::Circle { reflective, .. } | Obstacle::Wall { reflective, .. } => reflective,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "e", content = "data")]
 pub enum GameCommand {
     #[serde(rename = "rotate")]
@@ -38,9 +195,13 @@ pub enum GameCommand {
 
     #[serde(rename = "fire")]
     Fire, // Fire at the current angle.
+
+    #[serde(rename = "ready")]
+    Ready, // Ready for the match to start, only matters in the lobby.
 }
 
-#[derive(Debug, Serialize, Deserialize, Message)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
 #[serde(tag = "e", content = "data")]
 pub enum ServerToClient {
     #[serde(rename = "id")]
@@ -51,9 +212,239 @@ pub enum ServerToClient {
 
     #[serde(rename = "teamnames")]
     TeamNames(HashMap<u32, String>), // Send the game state to the client
+
+    #[serde(rename = "keyframe")]
+    Keyframe(Keyframe), // Send the full game state, starting a sequence of deltas
+
+    #[serde(rename = "delta")]
+    Delta(GameStateDelta), // Send the changes to the game state since the previous update
+
+    #[serde(rename = "ack")]
+    Ack(u64), // Tell the client their command was applied, at this game tick
+
+    #[serde(rename = "error")]
+    Error(CommandError), // Tell the client why their command had no effect
+
+    #[serde(rename = "status")]
+    Status(MatchStatus), // Tell the client where the match stands, whenever it changes
+
+    #[serde(rename = "game_over")]
+    GameOver(MatchResults), // Tell the client the final standings, once the match is over
+}
+
+/// What the matchmaking queue tells a bot waiting in it.
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
+#[serde(tag = "e", content = "data")]
+pub enum QueueToClient {
+    #[serde(rename = "waiting")]
+    Waiting(u32), // Tell the client how many bots are waiting, them included
+
+    #[serde(rename = "matched")]
+    Matched(String), // Tell the client the token of the room of their match
+}
+
+/// Where the match of a room stands.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
+pub struct MatchStatus {
+    pub status: GameStatus,
+    pub ready: Vec<u32>,        // IDs of the players ready to start, in the lobby
+    pub countdown: Option<u32>, // Seconds left before the match starts, during the countdown
+}
+
+/// What a player did during a match, on top of their score.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub survival_points: u32, // Points earned by staying alive
+}
+
+/// The final standings of a match, best player first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
+pub struct MatchResults {
+    pub standings: Vec<Standing>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<TeamStanding>, // Best team first, in team games
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Standing {
+    pub rank: u32, // Players with the same score share the same rank
+    pub player_id: u32,
+    pub name: String,
+    pub score: u32,
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+    #[serde(default)]
+    pub left: bool, // Left before the match was over
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TeamStanding {
+    pub rank: u32, // Teams with the same score share the same rank
+    pub team_id: u32,
+    pub name: String,
+    pub score: u32,
+    pub players: Vec<u32>,
+}
+
+impl MatchResults {
+    /// Ranks the players of `stats` by score, then by kills, and the `teams`
+    /// by score. The players of `left_players` are scored as they were when
+    /// they left.
+    pub fn new(
+        scoreboard: &HashMap<u32, u32>,
+        stats: &HashMap<u32, PlayerStats>,
+        left_players: &HashMap<u32, u32>,
+        team_names: &HashMap<u32, String>,
+        teams: &[Team],
+    ) -> Self {
+        let mut standings: Vec<Standing> = stats
+            .iter()
+            .map(|(&player_id, &stats)| Standing {
+                rank: 0,
+                player_id,
+                name: team_names.get(&player_id).cloned().unwrap_or_default(),
+                score: scoreboard
+                    .get(&player_id)
+                    .or_else(|| left_players.get(&player_id))
+                    .copied()
+                    .unwrap_or_default(),
+                stats,
+                left: left_players.contains_key(&player_id),
+            })
+            .collect();
+        standings.sort_by_key(|standing| {
+            (Reverse(standing.score), Reverse(standing.stats.kills), standing.player_id)
+        });
+
+        for index in 0..standings.len() {
+            standings[index].rank = match index {
+                0 => 1,
+                _ if standings[index].score == standings[index - 1].score => {
+                    standings[index - 1].rank
+                },
+                _ => index as u32 + 1,
+            };
+        }
+
+        let mut teams: Vec<TeamStanding> = teams
+            .iter()
+            .map(|team| TeamStanding {
+                rank: 0,
+                team_id: team.id,
+                name: team.name.clone(),
+                score: team.score,
+                players: team.players.clone(),
+            })
+            .collect();
+        teams.sort_by_key(|team| (Reverse(team.score), team.team_id));
+
+        for index in 0..teams.len() {
+            teams[index].rank = match index {
+                0 => 1,
+                _ if teams[index].score == teams[index - 1].score => teams[index - 1].rank,
+                _ => index as u32 + 1,
+            };
+        }
+
+        Self { standings, teams }
+    }
+
+    /// The rank of the team of a player, in team games.
+    pub fn team_rank(&self, player_id: u32) -> Option<u32> {
+        self.teams.iter().find(|team| team.players.contains(&player_id)).map(|team| team.rank)
+    }
+}
+
+/// Why a command sent by a client had no effect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandError {
+    #[serde(rename = "parse_error")]
+    ParseError, // The message isn't a valid `GameCommand`
+
+    #[serde(rename = "rate_limited")]
+    RateLimited, // Too many commands were sent, see `MIN_COMMAND_INTERVAL`
+
+    #[serde(rename = "not_running")]
+    NotRunning, // The game hasn't started yet, or is over
+
+    #[serde(rename = "not_in_game")]
+    NotInGame, // The player didn't get a slot in the game, e.g. it was full
+
+    #[serde(rename = "dead")]
+    Dead, // The player is waiting to respawn
+
+    #[serde(rename = "bullet_limit")]
+    BulletLimit, // The player already has as many bullets flying as they can
+
+    #[serde(rename = "unsupported_format")]
+    UnsupportedFormat, // Commands are only read from text messages, as JSON
+}
+
+/// How `ServerToClient` messages are encoded on the websocket, picked with the
+/// `format` query parameter when connecting. JSON is sent as text messages,
+/// MessagePack as binary messages.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WireFormat {
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+impl WireFormat {
+    /// The value of the `format` query parameter selecting this encoding.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+        }
+    }
+}
+
+impl std::str::FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(WireFormat::Json),
+            "msgpack" => Ok(WireFormat::MessagePack),
+            _ => Err(format!("Unknown wire format: {}", s)),
+        }
+    }
+}
+
+/// How the game state is sent to a client, picked with the `updates` query
+/// parameter when connecting.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateMode {
+    /// A full `GameState` every tick.
+    #[default]
+    #[serde(rename = "full")]
+    Full,
+
+    /// A `Keyframe` from time to time, and a `GameStateDelta` every other tick.
+    #[serde(rename = "delta")]
+    Delta,
+}
+
+impl UpdateMode {
+    /// The value of the `updates` query parameter selecting this mode.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UpdateMode::Full => "full",
+            UpdateMode::Delta => "delta",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct PlayerState {
     pub id: u32,
     pub angle: f32,
@@ -66,7 +457,7 @@ pub struct PlayerState {
     pub bullet_limit: u32,
 }
 
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
 pub struct BulletState {
     pub id: u32,
     pub player_id: u32,
@@ -77,20 +468,20 @@ pub struct BulletState {
     pub speed: f32,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct DeadPlayer {
-    pub respawn: SystemTime,
+    pub respawn_tick: u64, // The game tick at which the player comes back to life
     pub player: PlayerState,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum ItemType {
     FasterBullet,
     MoreBullet,
     BiggerBullet,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Item {
     pub id: u32,
     pub x: f32,
@@ -99,11 +490,23 @@ pub struct Item {
     pub item_type: ItemType,
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+/// A team of a team game, which its players score for together.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Team {
+    pub id: u32,
+    pub name: String,
+    pub players: Vec<u32>, // IDs of its players, dead or alive
+    pub score: u32,        // The sum of the scores of its players
+}
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum GameStatus {
     #[default]
-    New,
+    New, // In the lobby, waiting for the players
+    Countdown,
     Running,
+    Paused,
     Finished,
 }
 
@@ -123,41 +526,105 @@ impl GameStatus {
     }
 }
 
-#[derive(Clone, Default, Debug, Serialize, Deserialize, Message)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
 pub struct GameState {
+    #[serde(default)]
+    pub tick: u64, // The game tick this state was taken at, always increasing
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: f32, // Game ticks per second
+    #[serde(default)]
+    pub server_time: u64, // When the server sent this state, in milliseconds since the Unix epoch
+    #[serde(default)]
+    pub status: GameStatus,
+    #[serde(default)]
+    pub elapsed_seconds: f32, // Time played in the match, pauses excluded
+    #[serde(default)]
+    pub remaining_seconds: Option<f32>, // Time left in the match, if it has a time limit
     pub bounds: (f32, f32),
     pub players: Vec<PlayerState>,
     pub items: Vec<Item>,
     pub dead: Vec<DeadPlayer>,
     pub bullets: Vec<BulletState>,
     pub scoreboard: HashMap<u32, u32>,
+    #[serde(default)]
+    pub teams: Vec<Team>, // Empty unless it's a team game
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>, // They never move, so deltas leave them out
+    #[serde(default)]
+    pub rules: GameRules, // They never change either
+}
+
+/// The full game state, from which the following deltas are applied.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub seq: u64,
+    pub state: GameState,
+}
+
+/// The changes to the game state since the update numbered `seq - 1`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameStateDelta {
+    pub seq: u64,
+    pub tick: u64,
+    pub server_time: u64,
+    pub status: GameStatus,
+    pub elapsed_seconds: f32,
+    pub remaining_seconds: Option<f32>,
+    pub players: EntityDelta<PlayerState>,
+    pub items: EntityDelta<Item>,
+    pub dead: EntityDelta<DeadPlayer>,
+    pub bullets: EntityDelta<BulletState>,
+    pub scoreboard: EntityDelta<(u32, u32)>, // (player ID, score) pairs
+    pub teams: Option<Vec<Team>>,            // All of them, only when they changed
+}
+
+/// The changes to a list of entities. Removed entities are listed by ID.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntityDelta<T> {
+    pub added: Vec<T>,
+    pub changed: Vec<T>,
+    pub removed: Vec<u32>,
 }
 
 impl PlayerState {
-    pub fn new(id: u32) -> Self {
+    pub fn new(id: u32, rules: &GameRules) -> Self {
         Self {
             id,
             angle: 0f32,
             throttle: 0f32,
             x: 0f32,
             y: 0f32,
-            radius: PLAYER_BASE_RADIUS,
-            bullet_radius: BULLET_BASE_RADIUS,
-            bullet_speed: BULLET_BASE_SPEED,
-            bullet_limit: BULLET_BASE_LIMIT,
+            radius: rules.player_radius,
+            bullet_radius: rules.bullet_radius,
+            bullet_speed: rules.bullet_speed,
+            bullet_limit: rules.bullet_limit,
         }
     }
 
-    pub fn randomize(&mut self, rng: &mut impl rand::Rng, (bound_right, bound_bottom): (f32, f32)) {
+    /// How fast the player flies at full throttle: the bigger, the slower.
+    pub fn speed(&self, rules: &GameRules) -> f32 {
+        rules.player_speed - self.radius / 10.0
+    }
+
+    pub fn randomize(
+        &mut self,
+        rng: &mut impl rand::Rng,
+        (bound_right, bound_bottom): (f32, f32),
+        rules: &GameRules,
+        obstacles: &[Obstacle],
+    ) {
         self.angle = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
         self.throttle = 0.0;
-        self.x = rng.gen_range(0.0, bound_right);
-        self.y = rng.gen_range(0.0, bound_bottom);
+        let (x, y) =
+            random_position(rng, (bound_right, bound_bottom), rules.player_radius, obstacles);
+        self.x = x;
+        self.y = y;
         // reset stats
-        self.radius = PLAYER_BASE_RADIUS;
-        self.bullet_radius = BULLET_BASE_RADIUS;
-        self.bullet_speed = BULLET_BASE_SPEED;
-        self.bullet_limit = BULLET_BASE_LIMIT;
+        self.radius = rules.player_radius;
+        self.bullet_radius = rules.bullet_radius;
+        self.bullet_speed = rules.bullet_speed;
+        self.bullet_limit = rules.bullet_limit;
     }
 }
 
@@ -165,10 +632,10 @@ impl Item {
     pub fn new_randomized(
         id: u32,
         rng: &mut impl rand::Rng,
-        (bound_right, bound_bottom): (f32, f32),
+        bounds: (f32, f32),
+        obstacles: &[Obstacle],
     ) -> Self {
-        let x = rng.gen_range(0.0, bound_right);
-        let y = rng.gen_range(0.0, bound_bottom);
+        let (x, y) = random_position(rng, bounds, ITEM_RADIUS, obstacles);
         let item_type = match rng.gen_range(0, 3) {
             0 => ItemType::FasterBullet,
             1 => ItemType::MoreBullet,
@@ -185,7 +652,7 @@ impl Item {
                 player.radius *= PLAYER_RADIUS_INCREMENTAL;
             },
             ItemType::MoreBullet => {
-                player.bullet_limit += 1;
+                player.bullet_limit = player.bullet_limit.saturating_add(1);
                 player.radius *= PLAYER_RADIUS_INCREMENTAL;
             },
             ItemType::BiggerBullet => {
@@ -197,14 +664,111 @@ impl Item {
     }
 }
 
+/// A random position in the arena where a circle of `radius` doesn't overlap
+/// any of the obstacles. Gives up after `SPAWN_ATTEMPTS` tries, on arenas
+/// packed with obstacles, and takes the last one.
+fn random_position(
+    rng: &mut impl rand::Rng,
+    (bound_right, bound_bottom): (f32, f32),
+    radius: f32,
+    obstacles: &[Obstacle],
+) -> (f32, f32) {
+    let mut position = (0.0, 0.0);
+    for _ in 0..SPAWN_ATTEMPTS {
+        position = (rng.gen_range(0.0, bound_right), rng.gen_range(0.0, bound_bottom));
+        if !obstacles.iter().any(|obstacle| obstacle.overlaps(position.0, position.1, radius)) {
+            break;
+        }
+    }
+    position
+}
+
 impl GameState {
-    pub fn new(bounds: (f32, f32)) -> Self {
-        Self { bounds, ..Default::default() }
+    pub fn new(bounds: (f32, f32), tick_rate: f32) -> Self {
+        Self { bounds, tick_rate, ..Default::default() }
+    }
+
+    /// The team of a player, in team games.
+    pub fn team_of(&self, player_id: u32) -> Option<&Team> {
+        self.teams.iter().find(|team| team.players.contains(&player_id))
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Message)]
+impl Default for GameState {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            tick_rate: default_tick_rate(),
+            server_time: 0,
+            status: GameStatus::New,
+            elapsed_seconds: 0.0,
+            remaining_seconds: None,
+            bounds: (0.0, 0.0),
+            players: vec![],
+            items: vec![],
+            dead: vec![],
+            bullets: vec![],
+            scoreboard: HashMap::new(),
+            teams: vec![],
+            obstacles: vec![],
+            rules: GameRules::default(),
+        }
+    }
+}
+
+fn default_tick_rate() -> f32 {
+    crate::game::TICKS_PER_SECOND
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "net", derive(Message))]
 pub struct ClientState {
     pub id: u32,
     pub game_state: GameState,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rules_are_valid() {
+        assert_eq!(GameRules::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_out_of_range_rules() {
+        let out_of_range: [(&str, fn(&mut GameRules)); 10] = [
+            ("tick_rate", |rules| rules.tick_rate = MAX_TICK_RATE + 1.0),
+            ("player_radius", |rules| rules.player_radius = 0.0),
+            ("bullet_radius", |rules| rules.bullet_radius = f32::NAN),
+            ("respawn_delay_seconds", |rules| rules.respawn_delay_seconds = -1.0),
+            ("countdown_seconds", |rules| rules.countdown_seconds = f32::INFINITY),
+            ("player_speed", |rules| rules.player_speed = MAX_RULE_SPEED * 2.0),
+            ("reconnect_grace_seconds", |rules| rules.reconnect_grace_seconds = 1e30),
+            ("max_items", |rules| rules.max_items = MAX_RULE_ITEMS + 1),
+            ("bullet_limit", |rules| rules.bullet_limit = u32::MAX),
+            ("survival_points", |rules| rules.survival_points = u32::MAX),
+        ];
+
+        for (name, set) in out_of_range.iter() {
+            let mut rules = GameRules::default();
+            set(&mut rules);
+            let err = rules.validate().unwrap_err();
+            assert!(err.starts_with(name), "{} for {}", err, name);
+        }
+    }
+
+    #[test]
+    fn accepts_the_limits() {
+        let rules = GameRules {
+            tick_rate: MAX_TICK_RATE,
+            max_items: MAX_RULE_ITEMS,
+            bullet_limit: MAX_RULE_BULLETS,
+            survival_points: MAX_RULE_POINTS,
+            item_spawn_interval_seconds: 0.0,
+            ..GameRules::default()
+        };
+        assert_eq!(rules.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,124 @@
+//! An offline, in-process version of the game server. It runs `Handler`s
+//! against each other without any networking, advancing the game as fast as
+//! the CPU allows, which makes it handy to train and test bots in CI.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let rules = GameRules::default();
+//! let obstacles = vec![Obstacle::Circle { x: 500.0, y: 400.0, radius: 50.0, reflective: false }];
+//! let config = GameConfig { bound_x: 1000.0, bound_y: 800.0, seed: Some(42), rules, obstacles };
+//! let mut simulation = Simulation::new(config);
+//! simulation.add_bot("mine", MyPlayer::default());
+//! simulation.add_bot("theirs", TheirPlayer::default());
+//!
+//! for score in simulation.run_for(Duration::from_secs(120)) {
+//!     println!("{}: {}", score.name, score.score);
+//! }
+//! ```
+//!
+//! Note that the simulated time passes faster than the wall-clock time, so a
+//! `Handler` relying on `Instant::now()` will see distorted timings. Use the
+//! `tick` of the `GameState` instead, like `Analyzer` does.
+
+use crate::{
+    game::{duration_to_ticks, Game},
+    models::{ClientState, GameConfig, GameState, MIN_COMMAND_INTERVAL},
+    Handler,
+};
+use std::time::Duration;
+
+struct Bot {
+    id: u32,
+    name: String,
+    handler: Box<dyn Handler>,
+}
+
+/// The final score of a single bot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BotScore {
+    pub id: u32,
+    pub name: String,
+    pub score: u32,
+}
+
+/// Runs a `Game` and the `Handler`s playing it in the same thread.
+pub struct Simulation {
+    game: Game,
+    bots: Vec<Bot>,
+    // Simulated time, in seconds, at which the bots are given their next turn
+    next_turn_at: f32,
+}
+
+impl Simulation {
+    pub fn new(config: GameConfig) -> Self {
+        Self { game: Game::new(config), bots: vec![], next_turn_at: 0.0 }
+    }
+
+    /// Adds a bot to the game, and returns its player ID.
+    pub fn add_bot<H>(&mut self, name: &str, handler: H) -> u32
+    where
+        H: Handler + 'static,
+    {
+        let id = self.bots.len() as u32;
+        self.game.add_player(id);
+        self.bots.push(Bot { id, name: name.to_string(), handler: Box::new(handler) });
+
+        id
+    }
+
+    /// Returns the current state of the game.
+    pub fn state(&self) -> &GameState {
+        &self.game.state
+    }
+
+    /// Advances the game by a single tick. Like the `tokyo::run` loop, every
+    /// bot that is alive gets a turn each `MIN_COMMAND_INTERVAL`.
+    pub fn step(&mut self) {
+        let now = self.game.current_tick() as f32 / self.game.rules().tick_rate;
+
+        if now >= self.next_turn_at {
+            for bot in &mut self.bots {
+                let client_state = ClientState { id: bot.id, game_state: self.game.state.clone() };
+
+                if crate::is_player_alive(&client_state) {
+                    if let Some(command) = bot.handler.tick(&client_state) {
+                        let tick = self.game.current_tick();
+                        let result = self.game.handle_cmd(bot.id, command).map(|_| tick);
+                        bot.handler.on_command_result(result);
+                    }
+                }
+            }
+
+            self.next_turn_at += MIN_COMMAND_INTERVAL.as_secs_f32();
+        }
+
+        self.game.tick();
+    }
+
+    /// Runs the game for the given amount of simulated time, and returns the
+    /// scoreboard sorted by score, highest first.
+    pub fn run_for(&mut self, duration: Duration) -> Vec<BotScore> {
+        for _ in 0..duration_to_ticks(duration, self.game.rules().tick_rate) {
+            self.step();
+        }
+
+        self.scoreboard()
+    }
+
+    /// Returns the current scoreboard sorted by score, highest first.
+    pub fn scoreboard(&self) -> Vec<BotScore> {
+        let mut scores: Vec<BotScore> = self
+            .bots
+            .iter()
+            .map(|bot| BotScore {
+                id: bot.id,
+                name: bot.name.clone(),
+                score: *self.game.state.scoreboard.get(&bot.id).unwrap_or(&0),
+            })
+            .collect();
+        scores.sort_by(|a, b| b.score.cmp(&a.score).then(a.id.cmp(&b.id)));
+
+        scores
+    }
+}
@@ -0,0 +1,113 @@
+//! The guest side of hosted bots: a `Handler` compiled to WebAssembly, that
+//! the server plays inside the room loop. Most bots only need the
+//! `export_handler!` macro, in a `cdylib` crate built with:
+//!
+//! ```sh
+//! cargo build --release --target wasm32-unknown-unknown
+//! ```
+//!
+//! with `tokyo` depending on `default-features = false`. The module gets no
+//! imports from the server, and exports the following, with the game state and
+//! the commands encoded in JSON:
+//!
+//! - `memory`, its linear memory.
+//! - `tokyo_alloc(len: u32) -> u32`, a buffer of `len` bytes for the server to
+//!   write a `ClientState` or a command result into.
+//! - `tokyo_tick(ptr: u32, len: u32) -> u64`, takes the buffer with the
+//!   `ClientState` and returns the `GameCommand` to play, as its pointer in the
+//!   high 32 bits and its length in the low 32 bits, or 0 for none. The command
+//!   stays valid until the next call.
+//! - `tokyo_command_result(ptr: u32, len: u32)`, takes the buffer with the
+//!   outcome of the last command, e.g. `{"Ok":42}`. Optional.
+
+use crate::{
+    models::{ClientState, CommandError},
+    Handler,
+};
+use std::cell::RefCell;
+
+thread_local! {
+    // The last command returned to the server
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(vec![]) };
+}
+
+/// Exports the functions the server calls, playing with the given `Handler`.
+///
+/// ```ignore
+/// tokyo::export_handler!(MyBot::default());
+/// ```
+#[macro_export]
+macro_rules! export_handler {
+    ($handler:expr) => {
+        thread_local! {
+            static HANDLER: ::std::cell::RefCell<Box<dyn $crate::Handler>> =
+                ::std::cell::RefCell::new(Box::new($handler));
+        }
+
+        #[no_mangle]
+        pub extern "C" fn tokyo_alloc(len: u32) -> u32 {
+            $crate::wasm::alloc(len)
+        }
+
+        #[no_mangle]
+        pub extern "C" fn tokyo_tick(ptr: u32, len: u32) -> u64 {
+            HANDLER.with(|handler| unsafe {
+                $crate::wasm::tick(&mut **handler.borrow_mut(), ptr, len)
+            })
+        }
+
+        #[no_mangle]
+        pub extern "C" fn tokyo_command_result(ptr: u32, len: u32) {
+            HANDLER.with(|handler| unsafe {
+                $crate::wasm::command_result(&mut **handler.borrow_mut(), ptr, len)
+            })
+        }
+    };
+}
+
+/// Allocates a buffer of `len` bytes for the server to write into, to be
+/// handed back to `tick` or `command_result`, which free it.
+pub fn alloc(len: u32) -> u32 {
+    let buffer = vec![0u8; len as usize].into_boxed_slice();
+    Box::into_raw(buffer) as *mut u8 as u32
+}
+
+/// Takes back a buffer given out by `alloc`.
+unsafe fn take(ptr: u32, len: u32) -> Box<[u8]> {
+    Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr as *mut u8, len as usize))
+}
+
+/// Decodes the `ClientState` in the buffer, and gives the handler a turn.
+///
+/// # Safety
+///
+/// `ptr` and `len` must be a buffer given out by `alloc`, which is freed.
+pub unsafe fn tick(handler: &mut dyn Handler, ptr: u32, len: u32) -> u64 {
+    let input = take(ptr, len);
+    let state: ClientState = match serde_json::from_slice(&input) {
+        Ok(state) => state,
+        Err(_) => return 0,
+    };
+
+    match handler.tick(&state).and_then(|command| serde_json::to_vec(&command).ok()) {
+        Some(command) => OUTPUT.with(|output| {
+            let mut output = output.borrow_mut();
+            *output = command;
+            (output.as_ptr() as u64) << 32 | output.len() as u64
+        }),
+        None => 0,
+    }
+}
+
+/// Decodes the outcome of the last command in the buffer, and hands it to the
+/// handler.
+///
+/// # Safety
+///
+/// `ptr` and `len` must be a buffer given out by `alloc`, which is freed.
+pub unsafe fn command_result(handler: &mut dyn Handler, ptr: u32, len: u32) {
+    let input = take(ptr, len);
+    if let Ok(result) = serde_json::from_slice::<Result<u64, CommandError>>(&input) {
+        handler.on_command_result(result);
+    }
+}
@@ -1,30 +1,109 @@
 use crate::{
     actors::GameActor,
-    models::messages::{ClientStop, PlayerGameCommand},
+    models::messages::{ClientStop, PlayerGameCommand, StateUpdate},
     AppState,
 };
 use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
 use actix_web::ws::{self, CloseCode, CloseReason};
+use bytes::Bytes;
 use ratelimit_meter::{DirectRateLimiter, GCRA};
-use tokyo::models::ServerToClient;
+use std::sync::OnceLock;
+use tokyo::models::{
+    CommandError, GameState, GameStateDelta, ServerToClient, UpdateMode, WireFormat,
+};
 
 const ACTIONS_PER_SECOND: u32 = 22;
 
+/// The messages a `StateUpdate` is sent as, serialized by the first connection
+/// that needs each of them and shared with the other connections of the room.
+#[derive(Debug, Default)]
+pub struct EncodedUpdate {
+    messages: [OnceLock<Bytes>; 6], // Per `StateMessage` kind and `WireFormat`
+}
+
+/// Serializes like the `ServerToClient` message of the same name, but borrows
+/// the game state instead of owning a copy of it.
+#[derive(Serialize)]
+#[serde(tag = "e", content = "data")]
+enum StateMessage<'a> {
+    #[serde(rename = "state")]
+    GameState(&'a GameState),
+
+    #[serde(rename = "keyframe")]
+    Keyframe { seq: u64, state: &'a GameState },
+
+    #[serde(rename = "delta")]
+    Delta(&'a GameStateDelta),
+}
+
+impl StateMessage<'_> {
+    fn index(&self, format: WireFormat) -> usize {
+        let kind = match self {
+            StateMessage::GameState(_) => 0,
+            StateMessage::Keyframe { .. } => 1,
+            StateMessage::Delta(_) => 2,
+        };
+        match format {
+            WireFormat::Json => kind,
+            WireFormat::MessagePack => kind + 3,
+        }
+    }
+
+    fn encode(&self, format: WireFormat) -> Bytes {
+        match format {
+            WireFormat::Json => serde_json::to_vec(self).unwrap().into(),
+            WireFormat::MessagePack => rmp_serde::to_vec_named(self).unwrap().into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ClientWsActor {
     game_addr: Addr<GameActor>,
     api_key: String,
     team_name: String,
+    format: WireFormat,
+    updates: UpdateMode,
+    // Whether a keyframe was sent, so deltas can follow
+    synced: bool,
     rate_limiter: DirectRateLimiter<GCRA>,
 }
 
 impl ClientWsActor {
-    pub fn new(game_addr: Addr<GameActor>, api_key: String, team_name: String) -> ClientWsActor {
+    pub fn new(
+        game_addr: Addr<GameActor>,
+        api_key: String,
+        team_name: String,
+        format: WireFormat,
+        updates: UpdateMode,
+    ) -> ClientWsActor {
         let rate_limiter = DirectRateLimiter::<GCRA>::per_second(
             std::num::NonZeroU32::new(ACTIONS_PER_SECOND).unwrap(),
         );
 
-        ClientWsActor { game_addr, api_key, team_name, rate_limiter }
+        ClientWsActor {
+            game_addr,
+            api_key,
+            team_name,
+            format,
+            updates,
+            synced: false,
+            rate_limiter,
+        }
+    }
+
+    fn send(&self, msg: &ServerToClient, ctx: &mut <Self as Actor>::Context) {
+        match self.format {
+            WireFormat::Json => ctx.text(serde_json::to_string(msg).unwrap()),
+            WireFormat::MessagePack => ctx.binary(rmp_serde::to_vec_named(msg).unwrap()),
+        }
+    }
+
+    fn send_encoded(&self, msg: Bytes, ctx: &mut <Self as Actor>::Context) {
+        match self.format {
+            WireFormat::Json => ctx.text(msg),
+            WireFormat::MessagePack => ctx.binary(msg),
+        }
     }
 }
 
@@ -35,6 +114,7 @@ impl Actor for ClientWsActor {
         self.game_addr.do_send(crate::actors::game_actor::SocketEvent::Join(
             self.api_key.clone(),
             self.team_name.clone(),
+            self.updates,
             ctx.address(),
         ));
     }
@@ -51,18 +131,27 @@ impl Actor for ClientWsActor {
 impl StreamHandler<ws::Message, ws::ProtocolError> for ClientWsActor {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
         match msg {
+            ws::Message::Text(_) | ws::Message::Binary(_) if self.rate_limiter.check().is_err() => {
+                warn!("API key {} got rate limited", self.api_key);
+                self.send(&ServerToClient::Error(CommandError::RateLimited), ctx);
+            },
             ws::Message::Text(cmd) => {
-                if self.rate_limiter.check().is_ok() {
-                    let cmd_result = serde_json::from_str(&cmd);
+                let cmd_result = serde_json::from_str(&cmd);
 
-                    if let Ok(cmd) = cmd_result {
-                        self.game_addr
-                            .do_send(PlayerGameCommand { api_key: self.api_key.clone(), cmd });
-                    }
+                if let Ok(cmd) = cmd_result {
+                    self.game_addr.do_send(PlayerGameCommand {
+                        api_key: self.api_key.clone(),
+                        cmd,
+                        addr: ctx.address(),
+                    });
                 } else {
-                    warn!("API key {} got rate limited", self.api_key);
+                    self.send(&ServerToClient::Error(CommandError::ParseError), ctx);
                 }
             },
+            ws::Message::Binary(_) => {
+                // Commands are JSON, whatever the format of the updates
+                self.send(&ServerToClient::Error(CommandError::UnsupportedFormat), ctx);
+            },
             ws::Message::Close(_) => {
                 info!("API key {} close ws", self.api_key);
                 self.game_addr.do_send(crate::actors::game_actor::SocketEvent::Leave(
@@ -80,17 +169,63 @@ impl Handler<ServerToClient> for ClientWsActor {
     type Result = ();
 
     fn handle(&mut self, msg: ServerToClient, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_string(&msg).unwrap());
+        self.send(&msg, ctx);
+    }
+}
+
+impl Handler<StateUpdate> for ClientWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StateUpdate, ctx: &mut Self::Context) {
+        let message = match (self.updates, &msg.delta) {
+            (UpdateMode::Full, _) => StateMessage::GameState(&msg.state),
+            (UpdateMode::Delta, Some(delta)) if self.synced => StateMessage::Delta(delta),
+            (UpdateMode::Delta, _) => {
+                self.synced = true;
+                StateMessage::Keyframe { seq: msg.seq, state: &msg.state }
+            },
+        };
+
+        let encoded = msg.encoded.messages[message.index(self.format)]
+            .get_or_init(|| message.encode(self.format))
+            .clone();
+        self.send_encoded(encoded, ctx);
     }
 }
 
 impl Handler<ClientStop> for ClientWsActor {
     type Result = ();
 
-    fn handle(&mut self, _: ClientStop, ctx: &mut Self::Context) {
-        ctx.close(Some(CloseReason {
-            code: CloseCode::Normal,
-            description: Some("The server decided it didn't like you anymore. Or maybe you connected another client with the same API key".to_string())
-        }));
+    fn handle(&mut self, msg: ClientStop, ctx: &mut Self::Context) {
+        ctx.close(Some(CloseReason { code: CloseCode::Normal, description: Some(msg.reason) }));
+        ctx.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokyo::models::Keyframe;
+
+    #[test]
+    fn state_messages_encode_like_server_messages() {
+        let previous = GameState::default();
+        let state = GameState { server_time: 42, ..GameState::default() };
+        let delta = GameStateDelta::between(2, &previous, &state);
+
+        let pairs = vec![
+            (StateMessage::GameState(&state), ServerToClient::GameState(state.clone())),
+            (
+                StateMessage::Keyframe { seq: 1, state: &state },
+                ServerToClient::Keyframe(Keyframe { seq: 1, state: state.clone() }),
+            ),
+            (StateMessage::Delta(&delta), ServerToClient::Delta(delta.clone())),
+        ];
+        for (message, expected) in pairs {
+            let json = serde_json::to_vec(&expected).unwrap();
+            assert_eq!(message.encode(WireFormat::Json), json);
+            let msgpack = rmp_serde::to_vec_named(&expected).unwrap();
+            assert_eq!(message.encode(WireFormat::MessagePack), msgpack);
+        }
     }
 }
@@ -1,17 +1,44 @@
 use crate::{
-    actors::ClientWsActor,
-    game::{Game, TICKS_PER_SECOND},
-    models::messages::{ClientStop, PlayerGameCommand, ServerCommand},
+    actors::{ClientWsActor, RecordMatch},
+    house_bot::HouseBot,
+    ladder::{RatedMatch, RatedPlayer},
+    models::messages::{
+        AddHouseBots, ClientStop, CloseRoom, CommandResult, GetInactiveSince, GetResults, HostBot,
+        MatchOver, Player, PlayerGameCommand, RateMatches, ReplayControl, ServerCommand, SetTeams,
+        StateUpdate, TeamNames,
+    },
+    recording::{
+        MatchHeader, MatchRecorder, MatchRecording, RecordEntry, KEYFRAME_INTERVAL,
+        MATCH_FILE_VERSION,
+    },
+    wasm_bot::WasmBot,
 };
-use actix::{Actor, Addr, AsyncContext, Context, Handler, Message};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler, Message, MessageResult};
 use futures::sync::oneshot;
 use spin_sleep::LoopHelper;
 use std::{
     collections::{HashMap, HashSet},
-    sync::mpsc::{channel, Receiver, Sender},
-    time::{Duration, Instant},
+    iter::Peekable,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tokyo::models::*;
+use tokyo::{
+    game::{duration_to_ticks, rule_duration, ticks_to_duration, Game},
+    models::*,
+};
+
+const MIN_REPLAY_SPEED: f32 = 0.1;
+const MAX_REPLAY_SPEED: f32 = 16.0;
+
+// How often clients receiving deltas get a full game state
+const STATE_KEYFRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+const HOSTED_REASON: &str = "The server is already playing a bot uploaded with this API key";
+const KICKED_REASON: &str = "The server decided it didn't like you anymore. Or maybe you connected another client with the same API key";
 
 #[derive(Debug)]
 pub struct GameActor {
@@ -23,21 +50,67 @@ pub struct GameActor {
     msg_rx: Option<Receiver<GameLoopCommand>>,
     player_id_counter: u32,
     api_key_to_player_id: HashMap<String, u32>,
+    player_id_to_api_key: HashMap<u32, String>,
     game_config: GameConfig,
     max_players: u32,
     time_limit_seconds: u32,
+    recording_path: Option<PathBuf>,
+    replay: Option<Replay>,
+    replay_tx: Option<Sender<ReplayControl>>,
+    // When the last client left, None while somebody is connected
+    idle_since: Option<Instant>,
+    // API key -> when the player disconnected, they keep their place for a while
+    disconnected: HashMap<String, Instant>,
+    // API keys of the players whose bot is hosted by the server
+    hosted: HashSet<String>,
+    finished_at: Option<Instant>,
+    match_status: MatchStatus,
+    results: Option<MatchResults>, // Of the last finished match
+    ladder: Option<RateMatches>,
+    // API key -> ID of the team it plays for, in team games
+    api_key_to_team_id: HashMap<String, u32>,
+    // Connections and spectators receiving deltas, the loop only computes them
+    // while there are some
+    delta_connections: HashSet<Addr<ClientWsActor>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum GameLoopCommand {
     PlayerJoined(u32),
+    HouseBotJoined(u32), // Recorded as `PlayerJoined`
+    #[serde(skip)] // Recorded as `PlayerJoined`
+    HostedBotJoined(u32, Box<WasmBot>),
     PlayerLeft(u32),
+    SetTeams(Vec<String>), // Team names, in the order of their IDs
+    JoinTeam(u32, u32),    // Player ID, team ID
     GameCommand(u32, GameCommand),
     Reset,
+    TeamNames(HashMap<u32, String>), // Only used by the match recorder
+    Pause,
+    Resume,
+    EndMatch,
+    SetTimeLimit(u32), // In seconds
+    Start,             // Starts the countdown, even if not everybody is ready
+    #[serde(skip)] // Only matters to the connections of the room
+    SendDeltas(bool),
+}
+
+/// A recorded match waiting to be played back by `replay_loop`.
+#[derive(Debug)]
+struct Replay {
+    recording: MatchRecording,
+    control_rx: Receiver<ReplayControl>,
+    speed: f32,
+    paused: bool,
 }
 
 impl GameActor {
-    pub fn new(config: GameConfig, max_players: u32, time_limit_seconds: u32) -> GameActor {
+    pub fn new(
+        config: GameConfig,
+        max_players: u32,
+        time_limit_seconds: u32,
+        recording_path: Option<PathBuf>,
+    ) -> GameActor {
         let (msg_tx, msg_rx) = channel();
 
         GameActor {
@@ -49,11 +122,191 @@ impl GameActor {
             msg_rx: Some(msg_rx),
             player_id_counter: 0,
             api_key_to_player_id: HashMap::new(),
+            player_id_to_api_key: HashMap::new(),
             game_config: config,
             max_players,
             time_limit_seconds,
+            recording_path,
+            replay: None,
+            replay_tx: None,
+            idle_since: Some(Instant::now()),
+            disconnected: HashMap::new(),
+            hosted: HashSet::new(),
+            finished_at: None,
+            match_status: MatchStatus::default(),
+            results: None,
+            ladder: None,
+            api_key_to_team_id: HashMap::new(),
+            delta_connections: HashSet::new(),
+        }
+    }
+
+    /// Creates a game that plays back a recorded match to spectators instead
+    /// of running a live one.
+    pub fn replay(recording: MatchRecording, speed: f32, paused: bool) -> GameActor {
+        let (control_tx, control_rx) = channel();
+        let header = &recording.header;
+
+        let mut game_actor = GameActor::new(
+            header.config.clone(),
+            header.max_players,
+            header.time_limit_seconds,
+            None,
+        );
+        game_actor.replay = Some(Replay { recording, control_rx, speed, paused });
+        game_actor.replay_tx = Some(control_tx);
+        game_actor
+    }
+
+    fn send_to_game_loop(&self, cmd: GameLoopCommand) {
+        // The loop only goes away once the room is closed, after which the
+        // commands have nowhere to go anyway
+        if self.msg_tx.send(cmd).is_err() {
+            warn!("Dropped a command sent after the game loop stopped");
         }
     }
+
+    /// Tells the loop whether to compute deltas, when a connection receiving
+    /// them is the first to join or the last to leave.
+    fn update_delta_connections(&mut self, addr: Addr<ClientWsActor>, joined: bool) {
+        let was_empty = self.delta_connections.is_empty();
+        if joined {
+            self.delta_connections.insert(addr);
+        } else {
+            self.delta_connections.remove(&addr);
+        }
+
+        let send_deltas = !self.delta_connections.is_empty();
+        if send_deltas != !was_empty {
+            match &self.replay_tx {
+                Some(replay_tx) => {
+                    let _ = replay_tx.send(ReplayControl::SendDeltas(send_deltas));
+                },
+                None => self.send_to_game_loop(GameLoopCommand::SendDeltas(send_deltas)),
+            }
+        }
+    }
+
+    /// Takes a player out of the game, along with their score.
+    fn drop_player(&mut self, api_key: &str) {
+        self.disconnected.remove(api_key);
+        self.hosted.remove(api_key);
+
+        // Their player ID keeps pointing to their API key, for the ladder
+        if let Some(player_id) = self.api_key_to_player_id.remove(api_key) {
+            info!("player {} is gone for good", player_id);
+            self.send_to_game_loop(GameLoopCommand::PlayerLeft(player_id));
+        }
+    }
+
+    /// Adds a new player to the game, on the team of their API key if any.
+    fn join_game(&self, api_key: &str, joined: GameLoopCommand) {
+        let player_id = match joined {
+            GameLoopCommand::PlayerJoined(id) | GameLoopCommand::HostedBotJoined(id, _) => id,
+            _ => return,
+        };
+
+        self.send_to_game_loop(joined);
+        if let Some(&team_id) = self.api_key_to_team_id.get(api_key) {
+            self.send_to_game_loop(GameLoopCommand::JoinTeam(player_id, team_id));
+        }
+    }
+
+    /// The results of a match, for the ladder. Only the players with an API key
+    /// are rated, once each in case they rejoined under another player ID. In
+    /// team games, players are ranked with their team.
+    fn rated_match(&self, ladder: &RateMatches, results: &MatchResults) -> RatedMatch {
+        let mut players: Vec<RatedPlayer> = vec![];
+        for standing in &results.standings {
+            if let Some(api_key) = self.player_id_to_api_key.get(&standing.player_id) {
+                if players.iter().all(|player| player.api_key != *api_key) {
+                    players.push(RatedPlayer {
+                        api_key: api_key.clone(),
+                        name: standing.name.clone(),
+                        rank: results.team_rank(standing.player_id).unwrap_or(standing.rank),
+                        score: standing.score,
+                    });
+                }
+            }
+        }
+
+        RatedMatch {
+            finished_at: unix_millis() / 1000,
+            room_id: ladder.room_id,
+            room_name: ladder.room_name.clone(),
+            players,
+        }
+    }
+
+    fn update_idle_since(&mut self) {
+        // Hosted bots keep playing with nobody around
+        if self.connections.is_empty() && self.spectators.is_empty() && self.hosted.is_empty() {
+            self.idle_since.get_or_insert_with(Instant::now);
+        } else {
+            self.idle_since = None;
+        }
+    }
+}
+
+/// Measures how long a match has been running, leaving out the pauses.
+#[derive(Debug, Default)]
+struct MatchClock {
+    elapsed: Duration, // Before the last pause
+    resumed_at: Option<Instant>,
+}
+
+impl MatchClock {
+    fn resume(&mut self) {
+        self.resumed_at.get_or_insert_with(Instant::now);
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.elapsed += resumed_at.elapsed();
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.elapsed + self.resumed_at.map(|resumed_at| resumed_at.elapsed()).unwrap_or_default()
+    }
+}
+
+/// Turns the game state after each tick into a `StateUpdate`, with a keyframe
+/// every `STATE_KEYFRAME_INTERVAL` and deltas in between, as long as somebody
+/// receives deltas.
+struct StateEncoder {
+    seq: u64,
+    previous: Option<Arc<GameState>>,
+    keyframe_interval: u64,
+    send_deltas: bool,
+}
+
+impl StateEncoder {
+    fn new(tick_rate: f32) -> Self {
+        Self {
+            seq: 0,
+            previous: None,
+            keyframe_interval: duration_to_ticks(STATE_KEYFRAME_INTERVAL, tick_rate).max(1),
+            send_deltas: false,
+        }
+    }
+
+    fn encode(&mut self, state: &GameState) -> StateUpdate {
+        self.seq += 1;
+        let state = Arc::new(state.clone());
+
+        let delta = match &self.previous {
+            Some(previous)
+                if self.send_deltas && !self.seq.is_multiple_of(self.keyframe_interval) =>
+            {
+                Some(Arc::new(GameStateDelta::between(self.seq, previous, &state)))
+            },
+            _ => None,
+        };
+        self.previous = if self.send_deltas { Some(state.clone()) } else { None };
+
+        StateUpdate { seq: self.seq, state, delta, encoded: Arc::default() }
+    }
 }
 
 fn game_loop(
@@ -62,113 +315,433 @@ fn game_loop(
     mut cancel_chan: oneshot::Receiver<()>,
     config: GameConfig,
     max_players: u32,
-    time_limit_seconds: u32,
+    mut time_limit_seconds: u32,
+    recording_path: Option<PathBuf>,
 ) {
-    let mut loop_helper = LoopHelper::builder().build_with_target_rate(TICKS_PER_SECOND);
+    let tick_rate = config.rules.tick_rate;
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(tick_rate);
 
     let mut game = Game::new(config);
+    let keyframe_interval = duration_to_ticks(KEYFRAME_INTERVAL, tick_rate).max(1);
+
+    let mut recorder = recording_path.and_then(|path| {
+        let header = MatchHeader {
+            version: MATCH_FILE_VERSION,
+            config: game.config(),
+            max_players,
+            time_limit_seconds,
+        };
+
+        MatchRecorder::create(&path, &header)
+            .map_err(|err| warn!("Failed to create match file {:?}: {}", path, err))
+            .ok()
+    });
 
     game.init();
+    let mut encoder = StateEncoder::new(tick_rate);
     let mut status = GameStatus::New;
-    let mut num_players: u32 = 0;
-    let mut game_over_at: Option<Instant> = None;
+    let mut last_status = MatchStatus::default();
+    let mut player_ids = HashSet::new();
+    let mut ready_ids = HashSet::new();
+    let countdown = rule_duration(game.rules().countdown_seconds);
+    let mut countdown_ends_at: Option<Instant> = None;
+    let mut clock = MatchClock::default();
+    let mut start_requested = false;
+    let mut end_requested = false;
+    let mut bots: Vec<(u32, Box<dyn tokyo::Handler>)> = vec![];
+    let bot_turn_interval = duration_to_ticks(MIN_COMMAND_INTERVAL, tick_rate).max(1);
+    // The bots can't take up more than half a tick between them
+    let bots_time_budget = ticks_to_duration(1, tick_rate) / 2;
 
     loop {
         loop_helper.loop_start();
 
         match cancel_chan.try_recv() {
             Ok(Some(_)) | Err(_) => {
+                // The room is closed before the end of the match
+                record(&mut recorder, RecordEntry::End(game.current_tick()));
                 break;
             },
             _ => {},
         }
 
-        for cmd in msg_chan.try_iter() {
+        // The bots played by the server send their commands along with the
+        // ones received from websocket clients, under the same rules
+        let bot_turn = game.current_tick().is_multiple_of(bot_turn_interval);
+        let bot_cmds = if status.is_running() && bot_turn {
+            play_bots(&game, &mut bots, bots_time_budget)
+        } else {
+            vec![]
+        };
+
+        for cmd in bot_cmds.into_iter().chain(msg_chan.try_iter()) {
             // info!("Got a message! - {:?}", cmd);
             match cmd {
-                GameLoopCommand::PlayerJoined(id) => {
-                    if !can_add_player(&status, max_players, num_players) {
+                GameLoopCommand::PlayerJoined(id)
+                | GameLoopCommand::HouseBotJoined(id)
+                | GameLoopCommand::HostedBotJoined(id, _) => {
+                    if !can_add_player(&status, max_players, player_ids.len() as u32) {
                         continue;
                     }
+                    let bot: Option<Box<dyn tokyo::Handler>> = match cmd {
+                        GameLoopCommand::HouseBotJoined(_) => Some(Box::new(HouseBot::default())),
+                        // Compiled by the upload handler, out of the game loop
+                        GameLoopCommand::HostedBotJoined(_, bot) => Some(bot),
+                        _ => None,
+                    };
+                    if let Some(bot) = bot {
+                        // Bots are always ready to play
+                        ready_ids.insert(id);
+                        bots.push((id, bot));
+                    }
+                    let joined = GameLoopCommand::PlayerJoined(id);
+                    record(&mut recorder, RecordEntry::Command(game.current_tick(), joined));
                     game.add_player(id);
-                    num_players += 1;
-                    if can_start_game(&status, max_players, num_players) {
+                    player_ids.insert(id);
+                    if can_start_game(&status, max_players) {
                         println!("Starting game!");
                         status = GameStatus::Running;
-                        game_over_at =
-                            Some(Instant::now() + Duration::from_secs(time_limit_seconds as u64));
+                        clock.resume();
                     }
                 },
                 GameLoopCommand::PlayerLeft(id) => {
+                    // Players turned away from a full game leave too
+                    if !player_ids.remove(&id) {
+                        continue;
+                    }
+                    ready_ids.remove(&id);
+                    bots.retain(|(bot_id, _)| *bot_id != id);
+                    record(&mut recorder, RecordEntry::Command(game.current_tick(), cmd));
                     game.player_left(id);
-                    num_players -= 1;
                 },
-                GameLoopCommand::GameCommand(id, cmd) => {
+                GameLoopCommand::SetTeams(ref names) => {
+                    game.set_teams(names.clone());
+                    record(&mut recorder, RecordEntry::Command(game.current_tick(), cmd));
+                },
+                GameLoopCommand::JoinTeam(id, team_id) => {
+                    // Unless they were turned away from a full game
+                    if player_ids.contains(&id) {
+                        game.join_team(id, team_id);
+                        record(&mut recorder, RecordEntry::Command(game.current_tick(), cmd));
+                    }
+                },
+                GameLoopCommand::GameCommand(id, GameCommand::Ready) => {
+                    let result = if player_ids.contains(&id) {
+                        ready_ids.insert(id);
+                        Ok(game.current_tick())
+                    } else {
+                        Err(CommandError::NotInGame)
+                    };
+                    send_result(&game_actor, &mut bots, id, result);
+                },
+                GameLoopCommand::GameCommand(id, ref player_cmd) => {
                     if !status.is_running() {
+                        let result = Err(CommandError::NotRunning);
+                        send_result(&game_actor, &mut bots, id, result);
                         continue;
                     }
-                    game.handle_cmd(id, cmd);
+                    let tick = game.current_tick();
+                    let result = game.handle_cmd(id, player_cmd.clone()).map(|_| tick);
+                    send_result(&game_actor, &mut bots, id, result);
+                    record(&mut recorder, RecordEntry::Command(tick, cmd));
                 },
                 GameLoopCommand::Reset => {
+                    record(&mut recorder, RecordEntry::Command(game.current_tick(), cmd));
                     game.reset();
                 },
+                GameLoopCommand::TeamNames(team_names) => {
+                    record(&mut recorder, RecordEntry::TeamNames(game.current_tick(), team_names));
+                },
+                GameLoopCommand::Pause => {
+                    if status.is_running() {
+                        status = GameStatus::Paused;
+                        clock.pause();
+                    }
+                },
+                GameLoopCommand::Resume => {
+                    if let GameStatus::Paused = status {
+                        status = GameStatus::Running;
+                        clock.resume();
+                    }
+                },
+                GameLoopCommand::EndMatch => {
+                    if let GameStatus::Running | GameStatus::Paused = status {
+                        end_requested = true;
+                    }
+                },
+                GameLoopCommand::SetTimeLimit(seconds) => time_limit_seconds = seconds,
+                GameLoopCommand::Start => start_requested = true,
+                GameLoopCommand::SendDeltas(send_deltas) => encoder.send_deltas = send_deltas,
             }
         }
 
-        if will_end_game(&status, max_players, game_over_at) {
+        if status == GameStatus::New
+            && (start_requested || is_lobby_ready(max_players, &player_ids, &ready_ids))
+        {
+            info!("Starting countdown!");
+            status = GameStatus::Countdown;
+            countdown_ends_at = Some(Instant::now() + countdown);
+        }
+        start_requested = false;
+
+        if countdown_ends_at.is_some_and(|ends_at| Instant::now() >= ends_at) {
+            info!("Starting game!");
+            status = GameStatus::Running;
+            countdown_ends_at = None;
+            clock.resume();
+        }
+
+        let time_limit = Duration::from_secs(time_limit_seconds as u64);
+        if end_requested || will_end_game(&status, max_players, time_limit, clock.elapsed()) {
             println!("Ending game!");
             status = GameStatus::Finished;
-            game_over_at = None;
+            end_requested = false;
+            clock.pause();
+
+            game_actor.do_send(MatchOver {
+                scoreboard: game.state.scoreboard.clone(),
+                stats: game.stats().clone(),
+                left_players: game.left_players().clone(),
+                teams: game.state.teams.clone(),
+            });
+
+            // The match is over, close the recording
+            record(&mut recorder, RecordEntry::End(game.current_tick()));
+            recorder = None;
         }
 
         if status.is_running() {
-            let dt = 1.0 / TICKS_PER_SECOND;
-            game.tick(dt);
+            game.tick();
+
+            if game.current_tick().is_multiple_of(keyframe_interval) {
+                let keyframe =
+                    RecordEntry::Keyframe(game.current_tick(), Box::new(game.state.clone()));
+                record(&mut recorder, keyframe);
+            }
         }
 
         // Send out update packets
+        let mut ready: Vec<u32> = ready_ids.iter().copied().collect();
+        ready.sort_unstable();
+        let countdown = countdown_ends_at.map(|ends_at| {
+            ends_at.saturating_duration_since(Instant::now()).as_secs_f32().ceil() as u32
+        });
+        let match_status = MatchStatus { status, ready, countdown };
+        if match_status != last_status {
+            game_actor.do_send(match_status.clone());
+            last_status = match_status;
+        }
 
-        // TODO(bschwind) - maybe put the game state behind an Arc
-        //                  instead of cloning it
-        game_actor.do_send(game.state.clone());
+        game.state.status = status;
+        game.state.elapsed_seconds = clock.elapsed().as_secs_f32();
+        game.state.remaining_seconds = match max_players {
+            0 => None,
+            _ => Some(time_limit.saturating_sub(clock.elapsed()).as_secs_f32()),
+        };
+        game.state.server_time = unix_millis();
+        game_actor.do_send(encoder.encode(&game.state));
         loop_helper.loop_sleep();
     }
 
     info!("game over!");
 }
 
-fn can_add_player(status: &GameStatus, max_players: u32, num_players: u32) -> bool {
-    if max_players == 0 {
-        return true;
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+}
+
+/// Gives every bot played by the server that is alive a turn, like `tokyo::run`
+/// does for the bots playing over the network, returning their commands.
+/// Plays a turn of each bot alive, one after the other, until they used up
+/// `budget`. The others sit this turn out. Bots take turns going first, so
+/// that it's not always the same ones.
+fn play_bots(
+    game: &Game,
+    bots: &mut [(u32, Box<dyn tokyo::Handler>)],
+    budget: Duration,
+) -> Vec<GameLoopCommand> {
+    let started = Instant::now();
+    if !bots.is_empty() {
+        bots.rotate_left(1);
     }
-    match status {
-        GameStatus::New => num_players < max_players as u32,
-        _ => false,
+
+    let mut cmds = vec![];
+    let mut skipped = 0;
+    for (id, bot) in bots {
+        if !game.state.players.iter().any(|player| player.id == *id) {
+            continue;
+        }
+        if started.elapsed() >= budget {
+            skipped += 1;
+            continue;
+        }
+
+        let client_state = ClientState { id: *id, game_state: game.state.clone() };
+        if let Some(bot_cmd) = bot.tick(&client_state) {
+            cmds.push(GameLoopCommand::GameCommand(*id, bot_cmd));
+        }
+    }
+    if skipped > 0 {
+        warn!("Out of time for the bots, {} of them sat this turn out", skipped);
+    }
+    cmds
+}
+
+/// Hands the outcome of a command to the bot that sent it if the server plays
+/// it, or to the client that sent it otherwise.
+fn send_result(
+    game_actor: &Addr<GameActor>,
+    bots: &mut [(u32, Box<dyn tokyo::Handler>)],
+    player_id: u32,
+    result: Result<u64, CommandError>,
+) {
+    match bots.iter_mut().find(|(id, _)| *id == player_id) {
+        Some((_, bot)) => bot.on_command_result(result),
+        None => game_actor.do_send(CommandResult { player_id, result }),
+    }
+}
+
+fn record(recorder: &mut Option<MatchRecorder>, entry: RecordEntry) {
+    if let Some(recorder) = recorder {
+        recorder.record(&entry);
     }
 }
 
-fn can_start_game(status: &GameStatus, max_players: u32, num_players: u32) -> bool {
+fn replay_loop(
+    game_actor: Addr<GameActor>,
+    replay: Replay,
+    mut cancel_chan: oneshot::Receiver<()>,
+) {
+    let Replay { recording, control_rx, mut speed, mut paused } = replay;
+    let tick_rate = recording.header.config.rules.tick_rate;
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(tick_rate * speed);
+
+    let mut game = Game::new(recording.header.config);
+    let mut entries = recording.entries.into_iter().peekable();
+    let mut encoder = StateEncoder::new(tick_rate);
+    let mut pending_steps: u32 = 0;
+
+    loop {
+        loop_helper.loop_start();
+
+        match cancel_chan.try_recv() {
+            Ok(Some(_)) | Err(_) => {
+                break;
+            },
+            _ => {},
+        }
+
+        for control in control_rx.try_iter() {
+            match control {
+                ReplayControl::Speed(new_speed) => {
+                    speed = new_speed.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+                    loop_helper = LoopHelper::builder().build_with_target_rate(tick_rate * speed);
+                },
+                ReplayControl::Pause => paused = true,
+                ReplayControl::Resume => paused = false,
+                ReplayControl::Step => pending_steps += 1,
+                ReplayControl::SendDeltas(send_deltas) => encoder.send_deltas = send_deltas,
+            }
+        }
+
+        if !paused || pending_steps > 0 {
+            pending_steps = pending_steps.saturating_sub(1);
+
+            if !replay_tick(&game_actor, &mut game, &mut entries) {
+                // Stay on the final frame once the recording is over
+                paused = true;
+                pending_steps = 0;
+            }
+        }
+
+        game.state.server_time = unix_millis();
+        game_actor.do_send(encoder.encode(&game.state));
+        loop_helper.loop_sleep();
+    }
+
+    info!("replay over!");
+}
+
+/// Applies every entry recorded up to the current tick, then advances the game
+/// by one tick. Returns `false` once the end of the recording is reached.
+fn replay_tick(
+    game_actor: &Addr<GameActor>,
+    game: &mut Game,
+    entries: &mut Peekable<impl Iterator<Item = RecordEntry>>,
+) -> bool {
+    while let Some(entry) = entries.next_if(|entry| entry.tick() <= game.current_tick()) {
+        match entry {
+            RecordEntry::Command(_, cmd) => match cmd {
+                GameLoopCommand::PlayerJoined(id)
+                | GameLoopCommand::HouseBotJoined(id)
+                | GameLoopCommand::HostedBotJoined(id, _) => game.add_player(id),
+                GameLoopCommand::PlayerLeft(id) => game.player_left(id),
+                GameLoopCommand::SetTeams(names) => game.set_teams(names),
+                GameLoopCommand::JoinTeam(id, team_id) => game.join_team(id, team_id),
+                GameLoopCommand::GameCommand(id, cmd) => {
+                    let _ = game.handle_cmd(id, cmd);
+                },
+                GameLoopCommand::Reset => game.reset(),
+                GameLoopCommand::TeamNames(_)
+                | GameLoopCommand::Pause
+                | GameLoopCommand::Resume
+                | GameLoopCommand::EndMatch
+                | GameLoopCommand::SetTimeLimit(_)
+                | GameLoopCommand::Start
+                | GameLoopCommand::SendDeltas(_) => {},
+            },
+            // Resync on keyframes in case the simulation changed since the recording
+            RecordEntry::Keyframe(_, state) => game.state = *state,
+            RecordEntry::TeamNames(_, team_names) => game_actor.do_send(TeamNames(team_names)),
+            RecordEntry::End(_) => return false,
+        }
+    }
+
+    if entries.peek().is_none() {
+        return false;
+    }
+
+    game.tick();
+
+    // The match clock is only recorded in keyframes, keep it going in between
+    let dt = 1.0 / game.state.tick_rate;
+    game.state.elapsed_seconds += dt;
+    if let Some(remaining) = &mut game.state.remaining_seconds {
+        *remaining = (*remaining - dt).max(0.0);
+    }
+    true
+}
+
+fn can_add_player(status: &GameStatus, max_players: u32, num_players: u32) -> bool {
     if max_players == 0 {
         return true;
     }
     match status {
-        GameStatus::New => num_players == max_players as u32,
+        GameStatus::New => num_players < max_players as u32,
         _ => false,
     }
 }
 
-fn will_end_game(status: &GameStatus, max_players: u32, game_over_at: Option<Instant>) -> bool {
+// Rooms without a player limit have no lobby, they start with the first player
+fn can_start_game(status: &GameStatus, max_players: u32) -> bool {
+    max_players == 0 && *status == GameStatus::New
+}
+
+fn is_lobby_ready(max_players: u32, player_ids: &HashSet<u32>, ready_ids: &HashSet<u32>) -> bool {
+    max_players > 0 && player_ids.len() == max_players as usize && player_ids.is_subset(ready_ids)
+}
+
+fn will_end_game(
+    status: &GameStatus,
+    max_players: u32,
+    time_limit: Duration,
+    elapsed: Duration,
+) -> bool {
     if max_players == 0 {
         return false;
     }
     match status {
-        GameStatus::Running => {
-            if let Some(game_over_at) = game_over_at {
-                Instant::now() >= game_over_at
-            } else {
-                false
-            }
-        },
+        GameStatus::Running => elapsed >= time_limit,
         _ => false,
     }
 }
@@ -181,33 +754,49 @@ impl Actor for GameActor {
         let (cancel_tx, cancel_rx) = oneshot::channel();
         let addr = ctx.address();
 
+        self.cancel_chan = Some(cancel_tx);
+
+        if let Some(replay) = self.replay.take() {
+            std::thread::spawn(move || {
+                replay_loop(addr, replay, cancel_rx);
+            });
+            return;
+        }
+
         // "Take" the receiving end of the channel and give it
         // to the game loop thread
         let msg_rx = self.msg_rx.take().unwrap();
 
-        let config = self.game_config;
+        let config = self.game_config.clone();
         let max_players = self.max_players;
         let time_limit_seconds = self.time_limit_seconds;
+        let recording_path = self.recording_path.take();
         std::thread::spawn(move || {
-            game_loop(addr, msg_rx, cancel_rx, config, max_players, time_limit_seconds);
+            game_loop(
+                addr,
+                msg_rx,
+                cancel_rx,
+                config,
+                max_players,
+                time_limit_seconds,
+                recording_path,
+            );
         });
-
-        self.cancel_chan = Some(cancel_tx);
     }
 }
 
 #[derive(Debug, Message)]
 pub enum SocketEvent {
-    Join(String, String, Addr<ClientWsActor>),
+    Join(String, String, UpdateMode, Addr<ClientWsActor>),
     Leave(String, Addr<ClientWsActor>),
 }
 
 impl Handler<SocketEvent> for GameActor {
     type Result = ();
 
-    fn handle(&mut self, msg: SocketEvent, _ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: SocketEvent, ctx: &mut Self::Context) {
         match msg {
-            SocketEvent::Join(api_key, team_name, addr) => {
+            SocketEvent::Join(api_key, team_name, updates, addr) => {
                 let key_clone = api_key.clone();
                 let addr_clone = addr.clone();
 
@@ -215,13 +804,29 @@ impl Handler<SocketEvent> for GameActor {
 
                 if api_key == "SPECTATOR" {
                     addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
+                    if self.replay_tx.is_none() {
+                        addr.do_send(ServerToClient::Status(self.match_status.clone()));
+                    }
+                    if updates == UpdateMode::Delta {
+                        self.update_delta_connections(addr.clone(), true);
+                    }
                     self.spectators.insert(addr);
+                } else if self.replay_tx.is_some() {
+                    info!("refusing to let a player join a replay");
+                    addr.do_send(ClientStop { reason: KICKED_REASON.to_string() });
+                } else if self.hosted.contains(&api_key) {
+                    info!("refusing to let a hosted bot join over the network");
+                    addr.do_send(ClientStop { reason: HOSTED_REASON.to_string() });
                 } else {
+                    self.disconnected.remove(&api_key);
+                    if updates == UpdateMode::Delta {
+                        self.update_delta_connections(addr.clone(), true);
+                    }
                     let existing_client_opt = self.connections.insert(api_key, addr);
 
                     if let Some(existing_client) = existing_client_opt {
                         info!("kicking out old connection");
-                        existing_client.do_send(ClientStop {});
+                        existing_client.do_send(ClientStop { reason: KICKED_REASON.to_string() });
                     }
 
                     let player_id =
@@ -235,44 +840,52 @@ impl Handler<SocketEvent> for GameActor {
                             self.player_id_counter += 1;
                             info!("API key {} gets player ID {}", key_clone, player_id);
 
-                            self.api_key_to_player_id.insert(key_clone, player_id);
+                            self.api_key_to_player_id.insert(key_clone.clone(), player_id);
+                            self.player_id_to_api_key.insert(player_id, key_clone.clone());
 
-                            self.msg_tx
-                                .send(GameLoopCommand::PlayerJoined(player_id))
-                                .expect("The game loop should always be receiving commands");
+                            self.join_game(&key_clone, GameLoopCommand::PlayerJoined(player_id));
 
                             addr_clone.do_send(ServerToClient::Id(player_id));
                             player_id
                         };
+                    addr_clone.do_send(ServerToClient::Status(self.match_status.clone()));
 
                     // Update team name and broadcast new team names list to all sockets.
                     self.team_names.insert(player_id, team_name);
                     for addr in self.connections.values().chain(self.spectators.iter()) {
                         addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
                     }
+                    self.send_to_game_loop(GameLoopCommand::TeamNames(self.team_names.clone()));
                 }
             },
             SocketEvent::Leave(api_key, addr) => {
+                self.update_delta_connections(addr.clone(), false);
                 if api_key == "SPECTATOR" {
                     self.spectators.remove(&addr);
                 } else {
                     if let Some(client_addr) = self.connections.get(&api_key) {
                         if addr == *client_addr {
                             info!("person left - {:?}", api_key);
-
-                            if let Some(player_id) = self.api_key_to_player_id.get(&api_key) {
-                                self.msg_tx
-                                    .send(GameLoopCommand::PlayerLeft(*player_id))
-                                    .expect("The game loop should always be receiving commands");
-                            }
-
-                            self.api_key_to_player_id.remove(&api_key);
                             self.connections.remove(&api_key);
+
+                            // Keep their place in the game in case they reconnect
+                            let grace =
+                                rule_duration(self.game_config.rules.reconnect_grace_seconds);
+                            let disconnected_at = Instant::now();
+                            self.disconnected.insert(api_key.clone(), disconnected_at);
+                            ctx.run_later(grace, move |act, _ctx| {
+                                // Unless they reconnected and left again since
+                                if act.disconnected.get(&api_key) == Some(&disconnected_at) {
+                                    act.drop_player(&api_key);
+                                }
+                            });
                         }
                     }
                 }
             },
         }
+
+        self.update_idle_since();
     }
 }
 
@@ -280,34 +893,260 @@ impl Handler<PlayerGameCommand> for GameActor {
     type Result = ();
 
     fn handle(&mut self, msg: PlayerGameCommand, _ctx: &mut Self::Context) {
-        if let Some(player_id) = self.api_key_to_player_id.get(&msg.api_key) {
-            self.msg_tx
-                .send(GameLoopCommand::GameCommand(*player_id, msg.cmd))
-                .expect("The game loop should always be receiving commands");
+        match self.api_key_to_player_id.get(&msg.api_key) {
+            Some(player_id) => {
+                self.send_to_game_loop(GameLoopCommand::GameCommand(*player_id, msg.cmd))
+            },
+            // Spectators, and players turned away or taken out of the game
+            None => msg.addr.do_send(ServerToClient::Error(CommandError::NotInGame)),
         }
     }
 }
 
-impl Handler<GameState> for GameActor {
+impl Handler<StateUpdate> for GameActor {
     type Result = ();
 
-    fn handle(&mut self, msg: GameState, _ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: StateUpdate, _ctx: &mut Self::Context) {
         for addr in self.connections.values().chain(self.spectators.iter()) {
-            addr.do_send(ServerToClient::GameState(msg.clone()));
+            addr.do_send(msg.clone());
         }
     }
 }
 
-impl Handler<ServerCommand> for GameActor {
+impl Handler<CommandResult> for GameActor {
     type Result = ();
 
-    fn handle(&mut self, msg: ServerCommand, _ctx: &mut Self::Context) {
+    fn handle(&mut self, msg: CommandResult, _ctx: &mut Self::Context) {
+        let addr = self
+            .player_id_to_api_key
+            .get(&msg.player_id)
+            .and_then(|api_key| self.connections.get(api_key));
+
+        if let Some(addr) = addr {
+            addr.do_send(match msg.result {
+                Ok(tick) => ServerToClient::Ack(tick),
+                Err(err) => ServerToClient::Error(err),
+            });
+        }
+    }
+}
+
+impl Handler<ServerCommand> for GameActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: ServerCommand, _ctx: &mut Self::Context) -> Self::Result {
+        if self.replay_tx.is_some() {
+            return Err("Replays are controlled with their own endpoint".to_string());
+        }
+
         match msg {
-            ServerCommand::Reset => {
-                self.msg_tx
-                    .send(GameLoopCommand::Reset)
-                    .expect("The game loop should always be receiving commands");
+            ServerCommand::Reset => self.send_to_game_loop(GameLoopCommand::Reset),
+            ServerCommand::Pause => self.send_to_game_loop(GameLoopCommand::Pause),
+            ServerCommand::Resume => self.send_to_game_loop(GameLoopCommand::Resume),
+            ServerCommand::EndMatch => self.send_to_game_loop(GameLoopCommand::EndMatch),
+            ServerCommand::Start => self.send_to_game_loop(GameLoopCommand::Start),
+            ServerCommand::SetTimeLimit(seconds) => {
+                self.time_limit_seconds = seconds;
+                self.send_to_game_loop(GameLoopCommand::SetTimeLimit(seconds));
             },
+            ServerCommand::Kick(player) => {
+                let api_key = match player {
+                    // Unless they're gone and came back under another ID since
+                    Player::Id(player_id) => self
+                        .player_id_to_api_key
+                        .get(&player_id)
+                        .filter(|api_key| {
+                            self.api_key_to_player_id.get(*api_key) == Some(&player_id)
+                        })
+                        .cloned(),
+                    Player::ApiKey(api_key) => Some(api_key),
+                };
+                // Players waiting to reconnect can be kicked too
+                let api_key = api_key
+                    .filter(|api_key| {
+                        self.connections.contains_key(api_key)
+                            || self.hosted.contains(api_key)
+                            || self.disconnected.contains_key(api_key)
+                    })
+                    .ok_or_else(|| "Player not found".to_string())?;
+
+                // Kicked players don't get to keep their place, which also
+                // calls off their reconnect grace period
+                if let Some(addr) = self.connections.get(&api_key) {
+                    addr.do_send(ClientStop { reason: "You were kicked by an admin".to_string() });
+                }
+                self.drop_player(&api_key);
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler<TeamNames> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: TeamNames, _ctx: &mut Self::Context) {
+        self.team_names = msg.0;
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
         }
     }
 }
+
+impl Handler<ReplayControl> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReplayControl, _ctx: &mut Self::Context) {
+        if let Some(replay_tx) = &self.replay_tx {
+            let _ = replay_tx.send(msg);
+        }
+    }
+}
+
+impl Handler<MatchStatus> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MatchStatus, _ctx: &mut Self::Context) {
+        if msg.status == GameStatus::Finished {
+            self.finished_at.get_or_insert_with(Instant::now);
+        }
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::Status(msg.clone()));
+        }
+        self.match_status = msg;
+    }
+}
+
+impl Handler<AddHouseBots> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: AddHouseBots, _ctx: &mut Self::Context) {
+        if self.replay_tx.is_some() {
+            return;
+        }
+
+        for number in 1..=msg.count {
+            let player_id = self.player_id_counter;
+            self.player_id_counter += 1;
+            self.team_names.insert(player_id, format!("House bot {}", number));
+            self.send_to_game_loop(GameLoopCommand::HouseBotJoined(player_id));
+        }
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
+        }
+        self.send_to_game_loop(GameLoopCommand::TeamNames(self.team_names.clone()));
+    }
+}
+
+impl Handler<HostBot> for GameActor {
+    type Result = Result<u32, String>;
+
+    fn handle(&mut self, msg: HostBot, _ctx: &mut Self::Context) -> Self::Result {
+        if self.replay_tx.is_some() {
+            return Err("Bots can't join a replay".to_string());
+        }
+        if self.api_key_to_player_id.contains_key(&msg.api_key) {
+            return Err("This API key is already playing".to_string());
+        }
+
+        let player_id = self.player_id_counter;
+        self.player_id_counter += 1;
+        info!("API key {} gets player ID {} for its hosted bot", msg.api_key, player_id);
+
+        self.api_key_to_player_id.insert(msg.api_key.clone(), player_id);
+        self.player_id_to_api_key.insert(player_id, msg.api_key.clone());
+        self.join_game(&msg.api_key, GameLoopCommand::HostedBotJoined(player_id, msg.bot));
+        self.hosted.insert(msg.api_key);
+
+        self.team_names.insert(player_id, msg.team_name);
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::TeamNames(self.team_names.clone()));
+        }
+        self.send_to_game_loop(GameLoopCommand::TeamNames(self.team_names.clone()));
+        self.update_idle_since();
+
+        Ok(player_id)
+    }
+}
+
+impl Handler<MatchOver> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MatchOver, _ctx: &mut Self::Context) {
+        let results = MatchResults::new(
+            &msg.scoreboard,
+            &msg.stats,
+            &msg.left_players,
+            &self.team_names,
+            &msg.teams,
+        );
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ServerToClient::GameOver(results.clone()));
+        }
+        if let Some(ladder) = &self.ladder {
+            ladder.ladder.do_send(RecordMatch(self.rated_match(ladder, &results)));
+        }
+        self.results = Some(results);
+    }
+}
+
+impl Handler<SetTeams> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetTeams, _ctx: &mut Self::Context) {
+        let mut names = vec![];
+        for (team_id, team) in msg.0.into_iter().enumerate() {
+            for api_key in team.api_keys {
+                self.api_key_to_team_id.insert(api_key, team_id as u32);
+            }
+            names.push(team.name);
+        }
+        self.send_to_game_loop(GameLoopCommand::SetTeams(names));
+    }
+}
+
+impl Handler<RateMatches> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RateMatches, _ctx: &mut Self::Context) {
+        self.ladder = Some(msg);
+    }
+}
+
+impl Handler<GetResults> for GameActor {
+    type Result = MessageResult<GetResults>;
+
+    fn handle(&mut self, _: GetResults, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.results.clone())
+    }
+}
+
+impl Handler<GetInactiveSince> for GameActor {
+    type Result = MessageResult<GetInactiveSince>;
+
+    fn handle(&mut self, _: GetInactiveSince, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult([self.idle_since, self.finished_at].iter().flatten().min().copied())
+    }
+}
+
+impl Handler<CloseRoom> for GameActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseRoom, ctx: &mut Self::Context) {
+        info!("Closing the room: {}", msg.reason);
+
+        for addr in self.connections.values().chain(self.spectators.iter()) {
+            addr.do_send(ClientStop { reason: msg.reason.clone() });
+        }
+
+        if let Some(cancel_chan) = self.cancel_chan.take() {
+            let _ = cancel_chan.send(());
+        }
+
+        ctx.stop();
+    }
+}
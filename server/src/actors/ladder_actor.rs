@@ -0,0 +1,86 @@
+use crate::ladder::{HistoryEntry, Ladder, LadderEntry, RatedMatch};
+use actix::prelude::*;
+use std::collections::HashMap;
+
+// LadderActor rates the players of every match finished in a room, across rooms
+// and server restarts
+#[derive(Debug)]
+pub struct LadderActor {
+    ladder: Ladder,
+}
+
+impl LadderActor {
+    pub fn new(ladder: Ladder) -> LadderActor {
+        LadderActor { ladder }
+    }
+}
+
+impl Actor for LadderActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        info!("LadderActor started");
+    }
+}
+
+/// A finished match to rate, sent by the rooms.
+#[derive(Message)]
+pub struct RecordMatch(pub RatedMatch);
+
+impl Handler<RecordMatch> for LadderActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecordMatch, _ctx: &mut Self::Context) {
+        self.ladder.record(msg.0);
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Vec<LadderEntry>")]
+pub struct GetLadder;
+
+impl Handler<GetLadder> for LadderActor {
+    type Result = MessageResult<GetLadder>;
+
+    fn handle(&mut self, _msg: GetLadder, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.ladder.standings())
+    }
+}
+
+/// Gets the matches played with an API key, `None` if it never played one.
+#[derive(Message)]
+#[rtype(result = "Option<Vec<HistoryEntry>>")]
+pub struct GetHistory {
+    pub api_key: String,
+}
+
+impl Handler<GetHistory> for LadderActor {
+    type Result = MessageResult<GetHistory>;
+
+    fn handle(&mut self, msg: GetHistory, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.ladder.history(&msg.api_key))
+    }
+}
+
+/// Gets the ratings of API keys, by key.
+#[derive(Message)]
+#[rtype(result = "HashMap<String, f64>")]
+pub struct GetRatings {
+    pub api_keys: Vec<String>,
+}
+
+impl Handler<GetRatings> for LadderActor {
+    type Result = MessageResult<GetRatings>;
+
+    fn handle(&mut self, msg: GetRatings, _ctx: &mut Self::Context) -> Self::Result {
+        let ratings = msg
+            .api_keys
+            .into_iter()
+            .map(|api_key| {
+                let rating = self.ladder.rating(&api_key);
+                (api_key, rating)
+            })
+            .collect();
+        MessageResult(ratings)
+    }
+}
@@ -1,8 +1,16 @@
 pub mod client_ws_actor;
 pub mod game_actor;
+pub mod ladder_actor;
+pub mod queue_ws_actor;
 
 pub use client_ws_actor::ClientWsActor;
 pub use game_actor::GameActor;
+pub use ladder_actor::{GetHistory, GetLadder, GetRatings, LadderActor, RecordMatch};
+pub use queue_ws_actor::QueueWsActor;
 
 pub mod room_manager_actor;
-pub use room_manager_actor::{CreateRoom, JoinRoom, ListRooms, RoomManagerActor};
+pub use room_manager_actor::{
+    ApiKeys, ControlReplay, CreateReplay, CreateRoom, CreateTournament, DeleteRoom, GetRoomResults,
+    GetTournament, JoinQueue, JoinRoom, LeaveQueue, ListRooms, RoomAccess, RoomCommand,
+    RoomManagerActor, SpectateRoom,
+};
@@ -0,0 +1,73 @@
+use crate::{
+    actors::{JoinQueue, LeaveQueue, RoomManagerActor},
+    models::messages::ClientStop,
+    AppState,
+};
+use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
+use actix_web::ws::{self, CloseCode, CloseReason};
+use tokyo::models::QueueToClient;
+
+// QueueWsActor is a bot waiting in the matchmaking queue, until it's told which
+// room to join
+pub struct QueueWsActor {
+    room_manager_addr: Addr<RoomManagerActor>,
+    api_key: String,
+    team_name: String,
+}
+
+impl QueueWsActor {
+    pub fn new(
+        room_manager_addr: Addr<RoomManagerActor>,
+        api_key: String,
+        team_name: String,
+    ) -> QueueWsActor {
+        QueueWsActor { room_manager_addr, api_key, team_name }
+    }
+}
+
+impl Actor for QueueWsActor {
+    type Context = ws::WebsocketContext<Self, AppState>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.room_manager_addr.do_send(JoinQueue {
+            api_key: self.api_key.clone(),
+            team_name: self.team_name.clone(),
+            addr: ctx.address(),
+        });
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        self.room_manager_addr.do_send(LeaveQueue { addr: ctx.address() });
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for QueueWsActor {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        if let ws::Message::Close(_) = msg {
+            ctx.stop();
+        }
+    }
+}
+
+impl Handler<QueueToClient> for QueueWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: QueueToClient, ctx: &mut Self::Context) {
+        ctx.text(serde_json::to_string(&msg).unwrap());
+
+        if let QueueToClient::Matched(_) = msg {
+            let reason = "Matched".to_string();
+            ctx.close(Some(CloseReason { code: CloseCode::Normal, description: Some(reason) }));
+            ctx.stop();
+        }
+    }
+}
+
+impl Handler<ClientStop> for QueueWsActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientStop, ctx: &mut Self::Context) {
+        ctx.close(Some(CloseReason { code: CloseCode::Normal, description: Some(msg.reason) }));
+        ctx.stop();
+    }
+}
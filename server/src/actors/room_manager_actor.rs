@@ -1,19 +1,64 @@
-use crate::actors::GameActor;
+use crate::{
+    actors::{GameActor, GetRatings, LadderActor, QueueWsActor},
+    map::GameMap,
+    models::messages::{
+        AddHouseBots, ClientStop, CloseRoom, GetInactiveSince, GetResults, RateMatches,
+        ReplayControl, RoomTeam, ServerCommand, SetTeams,
+    },
+    queue::{MatchQueue, QueueConfig, QueuedPlayer},
+    recording::{MatchRecording, MATCH_FILE_EXTENSION},
+    tournament::{Entrant, MatchRoom, Tournament, TournamentFormat, TournamentProgress},
+};
 use actix::prelude::*;
+use futures::Future;
 use rand::{distributions::Alphanumeric, Rng};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io::{Error, ErrorKind, Result},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
 };
-use tokyo::models::GameConfig;
+use subtle::ConstantTimeEq;
+use tokyo::models::{GameConfig, GameRules, MatchResults, QueueToClient};
 
 const TOKEN_LENGTH: usize = 8;
+const OWNER_KEY_LENGTH: usize = 24;
+
+// How often rooms are checked for expiry
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// How many closed rooms keep the results of their last match around
+const MAX_PAST_RESULTS: usize = 1000;
+
+// How often the rooms of tournaments are checked for results
+const TOURNAMENT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// How often bots waiting in the queue are grouped into matches
+const QUEUE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The API keys allowed to join a room, each with the display name it's
+/// forced to, if any.
+pub type ApiKeys = HashMap<String, Option<String>>;
 
 // RoomManagerActor is responsible for creating and managing rooms
 pub struct RoomManagerActor {
     config: GameConfig,
+    recordings_dir: Option<PathBuf>,
+    // Rooms idle or finished for this long are closed, None to keep them forever
+    room_expiry: Option<Duration>,
+    // Rates the matches of every room when set
+    ladder: Option<Addr<LadderActor>>,
     id_counter: u32,
     rooms: HashMap<String, Room>,
+    // The results of the last match of closed rooms, by room ID, so the
+    // oldest rooms come first
+    past_results: BTreeMap<u32, PastResults>,
+    tournament_id_counter: u32,
+    tournaments: HashMap<u32, Tournament>,
+    // The matchmaking queue is disabled when not set
+    queue_config: Option<QueueConfig>,
+    queue: MatchQueue,
 }
 
 // Room is a single game instance
@@ -22,67 +67,483 @@ struct Room {
     name: String,
     max_players: u32,
     time_limit_seconds: u32,
-    token: String,
+    token: String,                  // Secret, needed to join the room
+    owner_key: String,              // Secret, needed to administer the room
+    private: bool,                  // Left out of the room list
+    api_keys: Option<Arc<ApiKeys>>, // The global API keys are used if not set
+    house_bots: u32,                // Bots played by the server
+    seed: u64,
+    rules: GameRules,
     game: Addr<GameActor>,
 }
 
+struct PastResults {
+    token: String,
+    private: bool,
+    results: MatchResults,
+}
+
 impl Room {
     pub fn new(
-        config: &GameConfig,
+        game_cfg: GameConfig,
         id: u32,
         name: String,
         max_players: u32,
         time_limit_seconds: u32,
         token: String,
+        recording_path: Option<PathBuf>,
     ) -> Room {
-        let game_cfg = GameConfig { bound_x: config.bound_x, bound_y: config.bound_y };
+        let seed = game_cfg.seed.unwrap_or_default();
+        let rules = game_cfg.rules;
 
-        let game_actor = GameActor::new(game_cfg, max_players, time_limit_seconds);
+        let game_actor = GameActor::new(game_cfg, max_players, time_limit_seconds, recording_path);
         let game_actor_addr = game_actor.start();
-        Room { id, name, max_players, time_limit_seconds, token, game: game_actor_addr }
+        Room {
+            id,
+            name,
+            max_players,
+            time_limit_seconds,
+            token,
+            owner_key: generate_token(OWNER_KEY_LENGTH),
+            private: false,
+            api_keys: None,
+            house_bots: 0,
+            seed,
+            rules,
+            game: game_actor_addr,
+        }
+    }
+
+    pub fn replay(
+        id: u32,
+        name: String,
+        token: String,
+        recording: MatchRecording,
+        speed: f32,
+        paused: bool,
+    ) -> Room {
+        let max_players = recording.header.max_players;
+        let time_limit_seconds = recording.header.time_limit_seconds;
+        let seed = recording.header.config.seed.unwrap_or_default();
+        let rules = recording.header.config.rules;
+
+        let game_actor = GameActor::replay(recording, speed, paused);
+        let game_actor_addr = game_actor.start();
+        Room {
+            id,
+            name,
+            max_players,
+            time_limit_seconds,
+            token,
+            owner_key: generate_token(OWNER_KEY_LENGTH),
+            private: false,
+            api_keys: None,
+            house_bots: 0,
+            seed,
+            rules,
+            game: game_actor_addr,
+        }
     }
 }
 
 impl RoomManagerActor {
-    pub fn new(cfg: GameConfig) -> RoomManagerActor {
-        RoomManagerActor { config: cfg, id_counter: 0, rooms: HashMap::new() }
+    pub fn new(
+        cfg: GameConfig,
+        recordings_dir: Option<PathBuf>,
+        room_expiry: Option<Duration>,
+        ladder: Option<Addr<LadderActor>>,
+        queue_config: Option<QueueConfig>,
+    ) -> RoomManagerActor {
+        RoomManagerActor {
+            config: cfg,
+            recordings_dir,
+            room_expiry,
+            ladder,
+            id_counter: 0,
+            rooms: HashMap::new(),
+            past_results: BTreeMap::new(),
+            tournament_id_counter: 0,
+            tournaments: HashMap::new(),
+            queue_config,
+            queue: MatchQueue::default(),
+        }
     }
 
-    pub fn create_room(
+    fn recording_path(&self, name: &str) -> Option<PathBuf> {
+        self.recordings_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.{}", name, MATCH_FILE_EXTENSION)))
+    }
+
+    pub fn create_room(&mut self, request: CreateRoom) -> RoomCreated {
+        let CreateRoom {
+            name,
+            max_players,
+            time_limit_seconds,
+            seed,
+            rules,
+            private,
+            api_keys,
+            house_bots,
+            teams,
+            map,
+        } = request;
+
+        self.id_counter += 1;
+        let token = generate_token(TOKEN_LENGTH);
+        // Pick the seed up front so the room can always be replayed from it
+        let seed = seed.or(self.config.seed).unwrap_or_else(rand::random);
+        let rules = rules.unwrap_or(self.config.rules);
+        let mut game_cfg = GameConfig { seed: Some(seed), rules, ..self.config.clone() };
+        if let Some(map) = map {
+            map.apply_to(&mut game_cfg);
+        }
+        let recording_path = self.recording_path(&token);
+
+        let mut room = Room::new(
+            game_cfg,
+            self.id_counter,
+            name,
+            max_players,
+            time_limit_seconds,
+            token.clone(),
+            recording_path,
+        );
+        room.private = private;
+        room.api_keys = api_keys.map(Arc::new);
+        room.house_bots = house_bots;
+        if !teams.is_empty() {
+            room.game.do_send(SetTeams(teams));
+        }
+        if house_bots > 0 {
+            room.game.do_send(AddHouseBots { count: house_bots });
+        }
+        if let Some(ladder) = &self.ladder {
+            room.game.do_send(RateMatches {
+                ladder: ladder.clone(),
+                room_id: room.id,
+                room_name: room.name.clone(),
+            });
+        }
+
+        let room_created = RoomCreated::from(&room);
+        self.rooms.insert(token, room);
+        room_created
+    }
+
+    pub fn create_replay(
         &mut self,
         name: String,
-        max_players: u32,
-        time_limit_seconds: u32,
-    ) -> RoomCreated {
+        recording: &str,
+        speed: f32,
+        paused: bool,
+    ) -> Result<RoomCreated> {
+        // Recordings are named after the token of the room they were recorded in
+        if !recording.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid recording name"));
+        }
+        let path = self
+            .recording_path(recording)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Recordings are disabled"))?;
+        let recording = MatchRecording::load(&path)?;
+
         self.id_counter += 1;
-        let new_id = self.id_counter.to_string();
-        let token: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(TOKEN_LENGTH)
-            .map(char::from)
+        let token = generate_token(TOKEN_LENGTH);
+        let room = Room::replay(self.id_counter, name, token.clone(), recording, speed, paused);
+
+        let room_created = RoomCreated::from(&room);
+        self.rooms.insert(token, room);
+
+        Ok(room_created)
+    }
+
+    /// Looks a room up by its public ID, checking that `access` allows
+    /// administering it. Returns the token of the room.
+    fn authorize(&self, room_id: u32, access: &RoomAccess) -> Result<String> {
+        let room = self
+            .rooms
+            .values()
+            .find(|room| room.id == room_id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Room not found"))?;
+
+        access.check(room)?;
+        Ok(room.token.clone())
+    }
+
+    /// Stops the game of a room and disconnects its clients with `reason`.
+    /// The results of its last match are kept around.
+    pub fn close_room(&mut self, token: &str, reason: &str, ctx: &mut Context<Self>) -> Result<()> {
+        let room = self
+            .rooms
+            .remove(token)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Room not found"))?;
+
+        info!("Closing room {} ({}): {}", room.id, room.name, reason);
+        let Room { id, token, private, .. } = room;
+        let save_results =
+            room.game.send(GetResults).into_actor(self).map(move |results, act, ctx| {
+                // A tournament match that can't finish anymore is over as it is
+                act.record_tournament_match(id, &results.clone().unwrap_or_default(), ctx);
+                if let Some(results) = results {
+                    act.past_results.insert(id, PastResults { token, private, results });
+                    while act.past_results.len() > MAX_PAST_RESULTS {
+                        act.past_results.pop_first();
+                    }
+                }
+            });
+        ctx.spawn(save_results.map_err(|_, _, _| ()));
+        room.game.do_send(CloseRoom { reason: reason.to_string() });
+        Ok(())
+    }
+
+    /// Finds a room by the token a client sent for it.
+    fn room_by_token(&self, token: &str) -> Option<&Room> {
+        self.rooms.values().find(|room| secrets_match(&room.token, token))
+    }
+
+    /// Gets the results of the last match of a room, still open or not.
+    /// Private rooms need their token.
+    fn results(
+        &self,
+        room_id: u32,
+        room_token: Option<&str>,
+    ) -> Box<dyn Future<Item = MatchResults, Error = Error>> {
+        let not_found = || Error::new(ErrorKind::NotFound, "Room not found");
+        let can_see = |private: bool, token: &str| {
+            !private || room_token.is_some_and(|room_token| secrets_match(token, room_token))
+        };
+
+        if let Some(room) = self.rooms.values().find(|room| room.id == room_id) {
+            if !can_see(room.private, &room.token) {
+                return Box::new(futures::future::err(not_found()));
+            }
+            return Box::new(
+                room.game
+                    .send(GetResults)
+                    .map_err(|_| Error::other("The room is closing"))
+                    .and_then(|results| {
+                        results.ok_or_else(|| {
+                            Error::new(ErrorKind::NotFound, "The match isn't over yet")
+                        })
+                    }),
+            );
+        }
+
+        let result = self
+            .past_results
+            .get(&room_id)
+            .filter(|past| can_see(past.private, &past.token))
+            .map(|past| past.results.clone())
+            .ok_or_else(not_found);
+        Box::new(futures::future::result(result))
+    }
+
+    fn close_expired_rooms(&mut self, ctx: &mut Context<Self>) {
+        let room_expiry = match self.room_expiry {
+            Some(room_expiry) => room_expiry,
+            None => return,
+        };
+
+        for (token, room) in &self.rooms {
+            let token = token.clone();
+            let check = room.game.send(GetInactiveSince).into_actor(self).map(
+                move |inactive_since, act, ctx| {
+                    if inactive_since.is_some_and(|since| since.elapsed() >= room_expiry) {
+                        let _ = act.close_room(&token, "The room expired", ctx);
+                    }
+                },
+            );
+            ctx.spawn(check.map_err(|_, _, _| ()));
+        }
+    }
+
+    pub fn create_tournament(
+        &mut self,
+        request: CreateTournament,
+        ctx: &mut Context<Self>,
+    ) -> std::result::Result<TournamentProgress, String> {
+        let CreateTournament { name, format, players, time_limit_seconds, rounds, rules } = request;
+
+        self.tournament_id_counter += 1;
+        let id = self.tournament_id_counter;
+        let mut tournament =
+            Tournament::new(id, name, format, players, time_limit_seconds, rounds)?;
+        tournament.rules = rules;
+        self.tournaments.insert(id, tournament);
+
+        self.open_tournament_rooms(id, ctx);
+        Ok(self.tournaments[&id].progress())
+    }
+
+    /// Creates the rooms of the matches of the current round of a tournament.
+    /// Only the players of a match are allowed in its room.
+    fn open_tournament_rooms(&mut self, tournament_id: u32, ctx: &mut Context<Self>) {
+        let tournament = match self.tournaments.get(&tournament_id) {
+            Some(tournament) => tournament,
+            None => return,
+        };
+
+        let requests: Vec<(usize, CreateRoom)> = tournament
+            .matches_without_room()
+            .into_iter()
+            .map(|(match_index, players)| {
+                let api_keys = players
+                    .iter()
+                    .map(|&player| tournament.entrant(player))
+                    .map(|entrant| (entrant.key.clone(), Some(entrant.name.clone())))
+                    .collect();
+                let request = CreateRoom {
+                    name: format!("{} - round {}", tournament.name, tournament.round()),
+                    max_players: players.len() as u32,
+                    time_limit_seconds: tournament.time_limit_seconds,
+                    seed: None,
+                    rules: tournament.rules,
+                    private: false,
+                    api_keys: Some(api_keys),
+                    house_bots: 0,
+                    teams: vec![],
+                    map: None,
+                };
+                (match_index, request)
+            })
             .collect();
 
-        self.rooms.insert(
-            token.clone(),
-            Room::new(
-                &self.config,
-                self.id_counter,
-                name.to_string(),
-                max_players,
+        for (match_index, request) in requests {
+            let token = self.create_room(request).token;
+            let room = MatchRoom { id: self.rooms[&token].id, token };
+            if let Some(tournament) = self.tournaments.get_mut(&tournament_id) {
+                tournament.set_room(match_index, room);
+            }
+        }
+
+        // Some results may be in already
+        self.check_tournaments(ctx);
+    }
+
+    /// Hands the results of a room to the tournament it was part of, if any,
+    /// which moves on to its next round once every match of the current one is
+    /// over.
+    fn record_tournament_match(
+        &mut self,
+        room_id: u32,
+        results: &MatchResults,
+        ctx: &mut Context<Self>,
+    ) {
+        let next_round: Vec<u32> = self
+            .tournaments
+            .values_mut()
+            .filter_map(|tournament| tournament.record(room_id, results).then_some(tournament.id))
+            .collect();
+
+        for tournament_id in next_round {
+            self.open_tournament_rooms(tournament_id, ctx);
+        }
+    }
+
+    fn check_tournaments(&mut self, ctx: &mut Context<Self>) {
+        let rooms_in_play = self.tournaments.values().flat_map(Tournament::rooms_in_play);
+
+        for MatchRoom { id, token } in rooms_in_play {
+            // Closed rooms report their results on their own
+            let room = match self.rooms.get(&token) {
+                Some(room) => room,
+                None => continue,
+            };
+
+            let check =
+                room.game.send(GetResults).into_actor(self).map(move |results, act, ctx| {
+                    if let Some(results) = results {
+                        act.record_tournament_match(id, &results, ctx);
+                    }
+                });
+            ctx.spawn(check.map_err(|_, _, _| ()));
+        }
+    }
+
+    fn join_queue(&mut self, player: QueuedPlayer) {
+        let addr = player.addr.clone();
+        match self.queue.join(player) {
+            Ok(()) => addr.do_send(QueueToClient::Waiting(self.queue.len() as u32)),
+            Err(reason) => addr.do_send(ClientStop { reason }),
+        }
+    }
+
+    /// Fetches the ratings of the bots waiting in the queue if there's a
+    /// ladder, then groups them into matches. Runs every few seconds rather
+    /// than as soon as enough bots are waiting, so there's a choice of
+    /// opponents when bots queue again together at the end of their matches.
+    fn match_queue(&mut self, ctx: &mut Context<Self>) {
+        let size = match self.queue_config {
+            Some(queue_config) => queue_config.players_per_match as usize,
+            None => return,
+        };
+        if self.queue.len() < size {
+            return;
+        }
+
+        match &self.ladder {
+            Some(ladder) => {
+                let ratings = ladder
+                    .send(GetRatings { api_keys: self.queue.api_keys() })
+                    .into_actor(self)
+                    .map(move |ratings, act, _ctx| act.open_queue_rooms(size, &ratings));
+                ctx.spawn(ratings.map_err(|_, _, _| ()));
+            },
+            None => self.open_queue_rooms(size, &HashMap::new()),
+        }
+    }
+
+    /// Creates a room for every group of `size` bots of the queue, and tells
+    /// them its token. Only the bots of a group are allowed in its room.
+    fn open_queue_rooms(&mut self, size: usize, ratings: &HashMap<String, f64>) {
+        let time_limit_seconds = match self.queue_config {
+            Some(queue_config) => queue_config.time_limit_seconds,
+            None => return,
+        };
+
+        while let Some(players) = self.queue.next_match(size, ratings) {
+            let api_keys = players
+                .iter()
+                .map(|player| (player.api_key.clone(), Some(player.name.clone())))
+                .collect();
+            let room = self.create_room(CreateRoom {
+                name: format!("Queue match {}", self.id_counter + 1),
+                max_players: size as u32,
                 time_limit_seconds,
-                token.clone(),
-            ),
-        );
+                seed: None,
+                rules: None,
+                private: false,
+                api_keys: Some(api_keys),
+                house_bots: 0,
+                teams: vec![],
+                map: None,
+            });
 
-        RoomCreated { id: new_id, name, max_players, time_limit_seconds, token }
+            for player in players {
+                player.addr.do_send(QueueToClient::Matched(room.token.clone()));
+            }
+        }
     }
 }
 
+fn generate_token(length: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(length).collect()
+}
+
+/// Compares a secret with what a client sent for it, in a time that doesn't
+/// depend on how much of it they got right.
+pub fn secrets_match(secret: &str, guess: &str) -> bool {
+    secret.as_bytes().ct_eq(guess.as_bytes()).into()
+}
+
 impl Actor for RoomManagerActor {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("RoomManagerActor started");
+        ctx.run_interval(EXPIRY_CHECK_INTERVAL, Self::close_expired_rooms);
+        ctx.run_interval(TOURNAMENT_CHECK_INTERVAL, Self::check_tournaments);
+        ctx.run_interval(QUEUE_CHECK_INTERVAL, Self::match_queue);
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -96,8 +557,16 @@ pub struct CreateRoom {
     pub name: String,
     pub max_players: u32,
     pub time_limit_seconds: u32,
+    pub seed: Option<u64>,
+    pub rules: Option<GameRules>,
+    pub private: bool,
+    pub api_keys: Option<ApiKeys>,
+    pub house_bots: u32,
+    pub teams: Vec<RoomTeam>, // Empty unless it's a team game
+    pub map: Option<GameMap>, // The arena of the config is used if not set
 }
 
+/// A new room, along with its secrets. Only its creator ever gets to see this.
 #[derive(Message, Deserialize, Serialize)]
 pub struct RoomCreated {
     pub id: String,
@@ -105,14 +574,226 @@ pub struct RoomCreated {
     pub max_players: u32,
     pub time_limit_seconds: u32,
     pub token: String,
+    pub owner_key: String,
+    pub private: bool,
+    pub house_bots: u32,
+    pub seed: u64,
+    pub rules: GameRules,
+}
+
+impl From<&Room> for RoomCreated {
+    fn from(room: &Room) -> Self {
+        RoomCreated {
+            id: room.id.to_string(),
+            name: room.name.clone(),
+            max_players: room.max_players,
+            time_limit_seconds: room.time_limit_seconds,
+            token: room.token.clone(),
+            owner_key: room.owner_key.clone(),
+            private: room.private,
+            house_bots: room.house_bots,
+            seed: room.seed,
+            rules: room.rules,
+        }
+    }
 }
 
 impl Handler<CreateRoom> for RoomManagerActor {
     type Result = MessageResult<CreateRoom>;
 
     fn handle(&mut self, msg: CreateRoom, _ctx: &mut Self::Context) -> Self::Result {
-        let room = self.create_room(msg.name, msg.max_players, msg.time_limit_seconds);
-        MessageResult(room)
+        MessageResult(self.create_room(msg))
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<RoomCreated>")]
+pub struct CreateReplay {
+    pub name: String,
+    pub recording: String,
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Handler<CreateReplay> for RoomManagerActor {
+    type Result = MessageResult<CreateReplay>;
+
+    fn handle(&mut self, msg: CreateReplay, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.create_replay(msg.name, &msg.recording, msg.speed, msg.paused))
+    }
+}
+
+/// Who is asking for an admin operation on a room.
+#[derive(Debug)]
+pub enum RoomAccess {
+    Admin,
+    Owner(String), // With the owner key of the room
+}
+
+impl RoomAccess {
+    fn check(&self, room: &Room) -> Result<()> {
+        match self {
+            RoomAccess::Admin => Ok(()),
+            RoomAccess::Owner(owner_key) if secrets_match(&room.owner_key, owner_key) => Ok(()),
+            RoomAccess::Owner(_) => {
+                Err(Error::new(ErrorKind::PermissionDenied, "Invalid room owner key"))
+            },
+        }
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct DeleteRoom {
+    pub room_id: u32,
+    pub access: RoomAccess,
+}
+
+impl Handler<DeleteRoom> for RoomManagerActor {
+    type Result = MessageResult<DeleteRoom>;
+
+    fn handle(&mut self, msg: DeleteRoom, ctx: &mut Self::Context) -> Self::Result {
+        let result = self
+            .authorize(msg.room_id, &msg.access)
+            .and_then(|token| self.close_room(&token, "The room was deleted", ctx));
+        MessageResult(result)
+    }
+}
+
+/// An admin operation on the room with the given ID.
+#[derive(Message)]
+#[rtype(result = "std::result::Result<(), String>")]
+pub struct RoomCommand {
+    pub room_id: u32,
+    pub access: RoomAccess,
+    pub cmd: ServerCommand,
+}
+
+impl Handler<RoomCommand> for RoomManagerActor {
+    type Result = ResponseFuture<(), String>;
+
+    fn handle(&mut self, msg: RoomCommand, _ctx: &mut Self::Context) -> Self::Result {
+        let token = match self.authorize(msg.room_id, &msg.access) {
+            Ok(token) => token,
+            Err(err) => return Box::new(futures::future::err(err.to_string())),
+        };
+        let room = self.rooms.get_mut(&token).expect("Authorized rooms exist");
+
+        if let ServerCommand::SetTimeLimit(seconds) = msg.cmd {
+            room.time_limit_seconds = seconds;
+        }
+
+        Box::new(
+            room.game
+                .send(msg.cmd)
+                .map_err(|_| "The room is closing".to_string())
+                .and_then(|result| result),
+        )
+    }
+}
+
+/// Controls the replay played in the room with the given token.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct ControlReplay {
+    pub room_token: String,
+    pub access: RoomAccess,
+    pub control: ReplayControl,
+}
+
+impl Handler<ControlReplay> for RoomManagerActor {
+    type Result = MessageResult<ControlReplay>;
+
+    fn handle(&mut self, msg: ControlReplay, _ctx: &mut Self::Context) -> Self::Result {
+        let result = self
+            .room_by_token(&msg.room_token)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Room not found"))
+            .and_then(|room| {
+                msg.access.check(room)?;
+                room.game.do_send(msg.control);
+                Ok(())
+            });
+        MessageResult(result)
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "std::result::Result<TournamentProgress, String>")]
+pub struct CreateTournament {
+    pub name: String,
+    pub format: TournamentFormat,
+    pub players: Vec<Entrant>, // In seeding order
+    pub time_limit_seconds: u32,
+    pub rounds: Option<u32>, // Of a Swiss tournament
+    pub rules: Option<GameRules>,
+}
+
+impl Handler<CreateTournament> for RoomManagerActor {
+    type Result = MessageResult<CreateTournament>;
+
+    fn handle(&mut self, msg: CreateTournament, ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.create_tournament(msg, ctx))
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "Option<TournamentProgress>")]
+pub struct GetTournament {
+    pub tournament_id: u32,
+}
+
+impl Handler<GetTournament> for RoomManagerActor {
+    type Result = MessageResult<GetTournament>;
+
+    fn handle(&mut self, msg: GetTournament, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.tournaments.get(&msg.tournament_id).map(Tournament::progress))
+    }
+}
+
+/// Gets the results of the last match of a room. Private rooms need their
+/// token.
+#[derive(Message)]
+#[rtype(result = "Result<MatchResults>")]
+pub struct GetRoomResults {
+    pub room_id: u32,
+    pub room_token: Option<String>,
+}
+
+impl Handler<GetRoomResults> for RoomManagerActor {
+    type Result = ResponseFuture<MatchResults, Error>;
+
+    fn handle(&mut self, msg: GetRoomResults, _ctx: &mut Self::Context) -> Self::Result {
+        self.results(msg.room_id, msg.room_token.as_deref())
+    }
+}
+
+/// Puts a bot connected to the `/queue` socket in line for a match.
+#[derive(Message)]
+pub struct JoinQueue {
+    pub api_key: String,
+    pub team_name: String,
+    pub addr: Addr<QueueWsActor>,
+}
+
+impl Handler<JoinQueue> for RoomManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: JoinQueue, _ctx: &mut Self::Context) {
+        let JoinQueue { api_key, team_name, addr } = msg;
+        self.join_queue(QueuedPlayer { api_key, name: team_name, addr });
+    }
+}
+
+#[derive(Message)]
+pub struct LeaveQueue {
+    pub addr: Addr<QueueWsActor>,
+}
+
+impl Handler<LeaveQueue> for RoomManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveQueue, _ctx: &mut Self::Context) {
+        self.queue.leave(&msg.addr);
     }
 }
 
@@ -125,15 +806,41 @@ pub struct JoinRoom {
 #[derive(Message)]
 pub struct RoomEntry {
     pub game_addr: Addr<GameActor>,
+    pub api_keys: Option<Arc<ApiKeys>>,
 }
 
 impl Handler<JoinRoom> for RoomManagerActor {
     type Result = MessageResult<JoinRoom>;
 
     fn handle(&mut self, msg: JoinRoom, _ctx: &mut Self::Context) -> Self::Result {
-        let room = self.rooms.get(&msg.room_token);
+        let room = self.room_by_token(&msg.room_token);
         match room {
-            Some(room) => MessageResult(Result::Ok(RoomEntry { game_addr: room.game.clone() })),
+            Some(room) => MessageResult(Result::Ok(RoomEntry {
+                game_addr: room.game.clone(),
+                api_keys: room.api_keys.clone(),
+            })),
+            None => MessageResult(Result::Err(Error::new(ErrorKind::NotFound, "Room not found"))),
+        }
+    }
+}
+
+/// Spectates a public room by its ID, without knowing its token.
+#[derive(Message)]
+#[rtype(result = "Result<RoomEntry>")]
+pub struct SpectateRoom {
+    pub room_id: u32,
+}
+
+impl Handler<SpectateRoom> for RoomManagerActor {
+    type Result = MessageResult<SpectateRoom>;
+
+    fn handle(&mut self, msg: SpectateRoom, _ctx: &mut Self::Context) -> Self::Result {
+        let room = self.rooms.values().find(|room| room.id == msg.room_id && !room.private);
+        match room {
+            Some(room) => MessageResult(Result::Ok(RoomEntry {
+                game_addr: room.game.clone(),
+                api_keys: room.api_keys.clone(),
+            })),
             None => MessageResult(Result::Err(Error::new(ErrorKind::NotFound, "Room not found"))),
         }
     }
@@ -154,7 +861,8 @@ pub struct RoomDetail {
     pub name: String,
     pub max_players: u32,
     pub time_limit_seconds: u32,
-    pub token: String,
+    pub house_bots: u32,
+    pub rules: GameRules,
 }
 
 impl Handler<ListRooms> for RoomManagerActor {
@@ -163,13 +871,15 @@ impl Handler<ListRooms> for RoomManagerActor {
     fn handle(&mut self, _msg: ListRooms, _ctx: &mut Self::Context) -> Self::Result {
         let mut rooms: Vec<RoomDetail> = self
             .rooms
-            .iter()
-            .map(|(_, room)| RoomDetail {
+            .values()
+            .filter(|room| !room.private)
+            .map(|room| RoomDetail {
                 id: room.id,
                 name: room.name.clone(),
                 max_players: room.max_players,
                 time_limit_seconds: room.time_limit_seconds,
-                token: room.token.clone(),
+                house_bots: room.house_bots,
+                rules: room.rules,
             })
             .collect();
         rooms.sort_by_key(|room| room.id);
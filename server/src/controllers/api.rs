@@ -1,67 +1,248 @@
 use crate::{
-    actors::{ClientWsActor, CreateRoom, JoinRoom, ListRooms},
-    models::messages::ServerCommand,
+    actors::{
+        room_manager_actor::secrets_match, ApiKeys, ClientWsActor, ControlReplay, CreateReplay,
+        CreateRoom, CreateTournament, DeleteRoom, GetHistory, GetLadder, GetRoomResults,
+        GetTournament, JoinRoom, LadderActor, ListRooms, QueueWsActor, RoomAccess, RoomCommand,
+        SpectateRoom,
+    },
+    map::GameMap,
+    models::messages::{HostBot, ReplayControl, RoomTeam, ServerCommand},
+    tournament::{Entrant, TournamentFormat},
+    wasm_bot::WasmBot,
     AppState,
 };
-use actix_web::{http::StatusCode, HttpRequest, Query, State};
+use actix_web::{
+    http::{header, StatusCode},
+    HttpMessage, HttpRequest, Path, Query, State,
+};
+use bytes::Bytes;
 use futures::Future;
+use std::{collections::HashSet, io::ErrorKind};
+use tokyo::models::{GameRules, MatchResults, UpdateMode, WireFormat};
+
+// Most bots the server plays in a single room
+const MAX_HOUSE_BOTS: u32 = 16;
+
+// Biggest WebAssembly module that can be uploaded as a hosted bot
+pub const MAX_BOT_MODULE_BYTES: usize = 8 * 1024 * 1024;
 
 #[derive(Debug, Deserialize)]
 pub struct QueryString {
     room_token: String,
     key: String,
     name: String,
+    #[serde(default)]
+    format: WireFormat,
+    #[serde(default)]
+    updates: UpdateMode,
 }
 
 pub fn socket_handler(
     (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<QueryString>),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    if crate::APP_CONFIG.dev_mode || crate::APP_CONFIG.api_keys.contains(&query.key) {
-        let r = state
-            .room_manager_addr
-            .send(JoinRoom { room_token: query.room_token.clone() })
-            .wait()
-            .unwrap();
-        match r {
-            Ok(room) => actix_web::ws::start(
+    let r = state
+        .room_manager_addr
+        .send(JoinRoom { room_token: query.room_token.clone() })
+        .wait()
+        .unwrap();
+    match r {
+        Ok(room) => {
+            let team_name = team_name(room.api_keys.as_deref(), &query.key, &query.name)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid API Key"))?;
+
+            actix_web::ws::start(
                 &req,
-                ClientWsActor::new(room.game_addr, query.key.clone(), query.name.clone()),
-            ),
-            Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
-        }
-    } else {
-        Err(actix_web::error::ErrorBadRequest("Invalid API Key"))
+                ClientWsActor::new(
+                    room.game_addr,
+                    query.key.clone(),
+                    team_name,
+                    query.format,
+                    query.updates,
+                ),
+            )
+        },
+        Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
+    }
+}
+
+/// Returns the name a player joining with `key` is shown with, or `None` if
+/// the key isn't allowed in the room. Rooms without their own API keys accept
+/// the global ones.
+fn team_name(room_api_keys: Option<&ApiKeys>, key: &str, name: &str) -> Option<String> {
+    match room_api_keys {
+        Some(api_keys) => api_keys
+            .iter()
+            .find(|(api_key, _)| secrets_match(api_key, key))
+            .map(|(_, fixed_name)| fixed_name.as_deref().unwrap_or(name).to_string()),
+        None if crate::APP_CONFIG.dev_mode
+            || crate::APP_CONFIG.api_keys.iter().any(|api_key| secrets_match(api_key, key)) =>
+        {
+            Some(name.to_string())
+        },
+        None => None,
     }
 }
 
 #[derive(Debug, Deserialize)]
-pub struct SpectatorString {
+pub struct QueueQuery {
+    key: String,
+    name: String,
+}
+
+/// Waits in the matchmaking queue until there are enough bots for a match,
+/// then sends the token of its room and closes.
+pub fn queue_handler(
+    (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<QueueQuery>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    if crate::APP_CONFIG.queue.is_none() {
+        return Err(actix_web::error::ErrorNotFound("The queue is disabled"));
+    }
+    let team_name = team_name(None, &query.key, &query.name)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid API Key"))?;
+
+    actix_web::ws::start(
+        &req,
+        QueueWsActor::new(state.room_manager_addr.clone(), query.key.clone(), team_name),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostBotQuery {
     room_token: String,
+    key: String,
+    name: String,
 }
 
-pub fn spectate_handler(
-    (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<SpectatorString>),
+#[derive(Debug, Serialize)]
+struct HostedBot {
+    player_id: u32,
+}
+
+/// Has the server play the bot in the WebAssembly module of the body, in place
+/// of a client connected to the socket with the same key.
+pub fn host_bot_handler(
+    (state, query, body): (State<AppState>, Query<HostBotQuery>, Bytes),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    // TODO(bschwind) - Make a separate spectator actor
-    let r = state
+    let room = state
         .room_manager_addr
         .send(JoinRoom { room_token: query.room_token.clone() })
         .wait()
-        .unwrap();
+        .unwrap()
+        .map_err(|err| actix_web::error::ErrorBadRequest(err.to_string()))?;
+    let team_name = team_name(room.api_keys.as_deref(), &query.key, &query.name)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid API Key"))?;
+
+    // Compiled here rather than by the game loop, which would stall every
+    // player of the room. Modules that can't play are turned away before they
+    // take a seat.
+    let bot = WasmBot::new(&body)
+        .map_err(|err| actix_web::error::ErrorBadRequest(format!("Invalid bot module: {}", err)))?;
+
+    let r = room
+        .game_addr
+        .send(HostBot { api_key: query.key.clone(), team_name, bot: Box::new(bot) })
+        .wait();
+    match r {
+        Ok(Ok(player_id)) => {
+            let body = serde_json::to_string(&HostedBot { player_id }).unwrap();
+            Ok(actix_web::HttpResponse::with_body(StatusCode::OK, body))
+        },
+        Ok(Err(err)) => Err(actix_web::error::ErrorBadRequest(err)),
+        Err(_) => Err(actix_web::error::ErrorBadRequest("Failed to host the bot")),
+    }
+}
+
+/// Public rooms can be spectated by ID, private ones need their token.
+#[derive(Debug, Deserialize)]
+pub struct SpectatorString {
+    room_token: Option<String>,
+    room_id: Option<u32>,
+    #[serde(default)]
+    format: WireFormat,
+    #[serde(default)]
+    updates: UpdateMode,
+}
+
+pub fn spectate_handler(
+    (req, state, query): (HttpRequest<AppState>, State<AppState>, Query<SpectatorString>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    // TODO(bschwind) - Make a separate spectator actor
+    let r = match (&query.room_token, query.room_id) {
+        (Some(room_token), _) => {
+            state.room_manager_addr.send(JoinRoom { room_token: room_token.clone() }).wait()
+        },
+        (None, Some(room_id)) => state.room_manager_addr.send(SpectateRoom { room_id }).wait(),
+        (None, None) => return Err(actix_web::error::ErrorBadRequest("Missing room_token")),
+    }
+    .unwrap();
     match r {
         Ok(room) => actix_web::ws::start(
             &req,
-            ClientWsActor::new(room.game_addr, "SPECTATOR".to_string(), "SPECTATOR".to_string()),
+            ClientWsActor::new(
+                room.game_addr,
+                "SPECTATOR".to_string(),
+                "SPECTATOR".to_string(),
+                query.format,
+                query.updates,
+            ),
         ),
         Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
     }
 }
 
-pub fn reset_handler(
-    (_req, state): (HttpRequest<AppState>, State<AppState>),
+/// Admin requests carry the `admin_key` of the config, or the owner key of
+/// the room they're about, in an `Authorization: Bearer <key>` header.
+fn bearer_key(req: &HttpRequest<AppState>) -> Option<&str> {
+    let authorization = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    authorization.strip_prefix("Bearer ")
+}
+
+/// The `admin_key` of the config. An empty one counts as not set, so that an
+/// empty bearer token never passes for it.
+fn admin_key() -> Option<&'static str> {
+    crate::APP_CONFIG.admin_key.as_deref().filter(|key| !key.is_empty())
+}
+
+fn check_admin_key(req: &HttpRequest<AppState>) -> Result<(), actix_web::Error> {
+    let admin_key = admin_key()
+        .ok_or_else(|| actix_web::error::ErrorForbidden("No admin key is configured"))?;
+
+    match bearer_key(req) {
+        Some(key) if secrets_match(admin_key, key) => Ok(()),
+        _ => Err(actix_web::error::ErrorUnauthorized("Invalid admin key")),
+    }
+}
+
+fn room_access(req: &HttpRequest<AppState>) -> Result<RoomAccess, actix_web::Error> {
+    let key = bearer_key(req)
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing admin or room owner key"))?;
+
+    if admin_key().is_some_and(|admin_key| secrets_match(admin_key, key)) {
+        Ok(RoomAccess::Admin)
+    } else {
+        Ok(RoomAccess::Owner(key.to_string()))
+    }
+}
+
+pub fn room_admin_handler(
+    (req, state, room_id, json): (
+        HttpRequest<AppState>,
+        State<AppState>,
+        Path<u32>,
+        actix_web::Json<ServerCommand>,
+    ),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    state.game_addr.do_send(ServerCommand::Reset);
-    Ok(actix_web::HttpResponse::with_body(StatusCode::OK, "done"))
+    let access = room_access(&req)?;
+
+    let r = state
+        .room_manager_addr
+        .send(RoomCommand { room_id: room_id.into_inner(), access, cmd: json.into_inner() })
+        .wait();
+    match r {
+        Ok(Ok(())) => Ok(actix_web::HttpResponse::with_body(StatusCode::OK, "done")),
+        Ok(Err(err)) => Err(actix_web::error::ErrorBadRequest(err)),
+        Err(_) => Err(actix_web::error::ErrorBadRequest("Failed to send the command")),
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -69,21 +250,105 @@ pub struct RoomCreateRequest {
     pub name: String,
     pub max_players: u32,
     pub time_limit_seconds: u32,
+    pub seed: Option<u64>,
+    pub rules: Option<GameRules>, // The rules of the server's config are used if not set
+    #[serde(default)]
+    pub private: bool, // Private rooms are left out of the room list
+    pub api_keys: Option<ApiKeys>, // The global API keys are used if not set
+    #[serde(default)]
+    pub house_bots: u32, // Bots played by the server, taking seats in the room
+    #[serde(default)]
+    pub teams: Vec<RoomTeam>, // Makes it a team game when set
+    pub map: Option<String>, // A map of the maps_dir, the arena of the config is used if not set
+}
+
+/// The API keys allowed in a team game: those of its teams. `api_keys` can
+/// still force the names of some of them.
+fn team_api_keys(teams: &[RoomTeam], api_keys: Option<&ApiKeys>) -> Result<ApiKeys, String> {
+    if teams.len() < 2 {
+        return Err("A team game needs at least 2 teams".to_string());
+    }
+
+    let mut names = HashSet::new();
+    let mut team_api_keys = ApiKeys::new();
+    for team in teams {
+        if team.api_keys.is_empty() {
+            return Err(format!("Team {} has no API keys", team.name));
+        }
+        if !names.insert(team.name.as_str()) {
+            return Err("Every team needs its own name".to_string());
+        }
+        for key in &team.api_keys {
+            let name = api_keys.and_then(|api_keys| api_keys.get(key).cloned()).flatten();
+            if team_api_keys.insert(key.clone(), name).is_some() {
+                return Err("An API key can only be in one team".to_string());
+            }
+        }
+    }
+
+    if let Some(api_keys) = api_keys {
+        if api_keys.keys().any(|key| !team_api_keys.contains_key(key)) {
+            return Err("Every API key of a team game needs a team".to_string());
+        }
+    }
+
+    Ok(team_api_keys)
 }
 
 pub fn create_room_handler(
-    (_req, state, json): (
+    (req, state, json): (
         HttpRequest<AppState>,
         State<AppState>,
         actix_web::Json<RoomCreateRequest>,
     ),
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    check_admin_key(&req)?;
+
+    if let Some(rules) = &json.rules {
+        rules.validate().map_err(actix_web::error::ErrorBadRequest)?;
+    }
+    if json.house_bots > MAX_HOUSE_BOTS {
+        let message = format!("house_bots must be at most {}", MAX_HOUSE_BOTS);
+        return Err(actix_web::error::ErrorBadRequest(message));
+    }
+    if json.max_players > 0 && json.house_bots >= json.max_players {
+        return Err(actix_web::error::ErrorBadRequest("house_bots must leave a seat for players"));
+    }
+    let map = match &json.map {
+        Some(name) => {
+            let maps_dir = crate::APP_CONFIG
+                .maps_dir
+                .as_ref()
+                .ok_or_else(|| actix_web::error::ErrorNotFound("Maps are disabled"))?;
+            let map = GameMap::load(maps_dir, name).map_err(|err| match err.kind() {
+                ErrorKind::NotFound => actix_web::error::ErrorNotFound("Map not found"),
+                _ => actix_web::error::ErrorBadRequest(format!("Invalid map: {}", err)),
+            })?;
+            Some(map)
+        },
+        None => None,
+    };
+    let api_keys = if json.teams.is_empty() {
+        json.api_keys.clone()
+    } else {
+        let api_keys = team_api_keys(&json.teams, json.api_keys.as_ref())
+            .map_err(actix_web::error::ErrorBadRequest)?;
+        Some(api_keys)
+    };
+
     let r = state
         .room_manager_addr
         .send(CreateRoom {
             name: json.name.clone(),
             max_players: json.max_players,
             time_limit_seconds: json.time_limit_seconds,
+            seed: json.seed,
+            rules: json.rules,
+            private: json.private,
+            api_keys,
+            house_bots: json.house_bots,
+            teams: json.teams.clone(),
+            map,
         })
         .wait();
     match r {
@@ -107,3 +372,252 @@ pub fn list_rooms_handler(
         Err(_) => Err(actix_web::error::ErrorBadRequest("Failed to list rooms")),
     }
 }
+
+pub fn delete_room_handler(
+    (req, state, room_id): (HttpRequest<AppState>, State<AppState>, Path<u32>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let access = room_access(&req)?;
+
+    let r = state
+        .room_manager_addr
+        .send(DeleteRoom { room_id: room_id.into_inner(), access })
+        .wait()
+        .unwrap();
+    match r {
+        Ok(()) => Ok(actix_web::HttpResponse::with_body(StatusCode::OK, "done")),
+        Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultsFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResultsQuery {
+    room_token: Option<String>, // Needed for private rooms
+    #[serde(default)]
+    format: ResultsFormat,
+}
+
+pub fn room_results_handler(
+    (_req, state, room_id, query): (
+        HttpRequest<AppState>,
+        State<AppState>,
+        Path<u32>,
+        Query<ResultsQuery>,
+    ),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let r = state
+        .room_manager_addr
+        .send(GetRoomResults {
+            room_id: room_id.into_inner(),
+            room_token: query.room_token.clone(),
+        })
+        .wait()
+        .unwrap();
+    match (r, &query.format) {
+        (Ok(results), ResultsFormat::Json) => Ok(actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&results).unwrap())),
+        (Ok(results), ResultsFormat::Csv) => {
+            Ok(actix_web::HttpResponse::Ok().content_type("text/csv").body(results_csv(&results)))
+        },
+        (Err(err), _) => Err(actix_web::error::ErrorNotFound(err.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TournamentCreateRequest {
+    pub name: String,
+    pub format: TournamentFormat,
+    pub players: Vec<Entrant>,    // In seeding order
+    pub time_limit_seconds: u32,  // Of each match
+    pub rounds: Option<u32>,      // Of a Swiss tournament, enough to single out a winner if not set
+    pub rules: Option<GameRules>, // The rules of the server's config are used if not set
+}
+
+pub fn create_tournament_handler(
+    (req, state, json): (
+        HttpRequest<AppState>,
+        State<AppState>,
+        actix_web::Json<TournamentCreateRequest>,
+    ),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    check_admin_key(&req)?;
+
+    if let Some(rules) = &json.rules {
+        rules.validate().map_err(actix_web::error::ErrorBadRequest)?;
+    }
+
+    let json = json.into_inner();
+    let r = state
+        .room_manager_addr
+        .send(CreateTournament {
+            name: json.name,
+            format: json.format,
+            players: json.players,
+            time_limit_seconds: json.time_limit_seconds,
+            rounds: json.rounds,
+            rules: json.rules,
+        })
+        .wait();
+    match r {
+        Ok(Ok(progress)) => {
+            let body = serde_json::to_string(&progress).unwrap();
+            Ok(actix_web::HttpResponse::with_body(StatusCode::OK, body))
+        },
+        Ok(Err(err)) => Err(actix_web::error::ErrorBadRequest(err)),
+        Err(_) => Err(actix_web::error::ErrorBadRequest("Failed to create tournament")),
+    }
+}
+
+pub fn tournament_handler(
+    (_req, state, tournament_id): (HttpRequest<AppState>, State<AppState>, Path<u32>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let progress = state
+        .room_manager_addr
+        .send(GetTournament { tournament_id: tournament_id.into_inner() })
+        .wait()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Failed to get the tournament"))?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Tournament not found"))?;
+
+    Ok(actix_web::HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&progress).unwrap()))
+}
+
+fn ladder(state: &AppState) -> Result<&actix::Addr<LadderActor>, actix_web::Error> {
+    state
+        .ladder_addr
+        .as_ref()
+        .ok_or_else(|| actix_web::error::ErrorNotFound("The ladder is disabled"))
+}
+
+pub fn ladder_handler(
+    (_req, state): (HttpRequest<AppState>, State<AppState>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    match ladder(&state)?.send(GetLadder).wait() {
+        Ok(ladder) => Ok(actix_web::HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&ladder).unwrap())),
+        Err(_) => Err(actix_web::error::ErrorBadRequest("Failed to get the ladder")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    key: String,
+}
+
+/// The history of an API key is only shown to those who know it.
+pub fn ladder_history_handler(
+    (_req, state, query): (HttpRequest<AppState>, State<AppState>, Query<HistoryQuery>),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let history = ladder(&state)?
+        .send(GetHistory { api_key: query.key.clone() })
+        .wait()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Failed to get the history"))?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("No match played with this key"))?;
+
+    Ok(actix_web::HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&history).unwrap()))
+}
+
+/// One line per team, then one per player. Team lines leave the stats out,
+/// player lines name their team, if any, and whether they left the match.
+fn results_csv(results: &MatchResults) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    let team_of = |player_id: u32| {
+        let team = results.teams.iter().find(|team| team.players.contains(&player_id));
+        team.map_or(String::new(), |team| team.team_id.to_string())
+    };
+
+    let mut csv = "kind,rank,id,name,score,kills,deaths,survival_points,team_id,left\n".to_string();
+    for team in &results.teams {
+        csv += &format!(
+            "team,{},{},{},{},,,,,\n",
+            team.rank,
+            team.team_id,
+            quote(&team.name),
+            team.score
+        );
+    }
+    for standing in &results.standings {
+        csv += &format!(
+            "player,{},{},{},{},{},{},{},{},{}\n",
+            standing.rank,
+            standing.player_id,
+            quote(&standing.name),
+            standing.score,
+            standing.stats.kills,
+            standing.stats.deaths,
+            standing.stats.survival_points,
+            team_of(standing.player_id),
+            standing.left,
+        );
+    }
+    csv
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayCreateRequest {
+    pub name: String,
+    pub recording: String,
+    pub speed: Option<f32>,
+    pub paused: Option<bool>,
+}
+
+pub fn create_replay_handler(
+    (req, state, json): (
+        HttpRequest<AppState>,
+        State<AppState>,
+        actix_web::Json<ReplayCreateRequest>,
+    ),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    check_admin_key(&req)?;
+
+    let r = state
+        .room_manager_addr
+        .send(CreateReplay {
+            name: json.name.clone(),
+            recording: json.recording.clone(),
+            speed: json.speed.unwrap_or(1.0),
+            paused: json.paused.unwrap_or(false),
+        })
+        .wait()
+        .unwrap();
+    match r {
+        Ok(room) => {
+            let body = serde_json::to_string(&room).unwrap();
+            Ok(actix_web::HttpResponse::with_body(StatusCode::OK, body))
+        },
+        Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
+    }
+}
+
+pub fn replay_control_handler(
+    (req, state, token, json): (
+        HttpRequest<AppState>,
+        State<AppState>,
+        Path<String>,
+        actix_web::Json<ReplayControl>,
+    ),
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let access = room_access(&req)?;
+
+    let r = state
+        .room_manager_addr
+        .send(ControlReplay { room_token: token.into_inner(), access, control: json.into_inner() })
+        .wait()
+        .unwrap();
+    match r {
+        Ok(()) => Ok(actix_web::HttpResponse::with_body(StatusCode::OK, "done")),
+        Err(err) => Err(actix_web::error::ErrorBadRequest(err.to_string())),
+    }
+}
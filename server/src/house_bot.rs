@@ -0,0 +1,53 @@
+use tokyo::{
+    analyzer::Analyzer,
+    behavior::{Behavior, Chase, Dodge, FireAt, Sequence, Target},
+    models::{ClientState, GameCommand},
+    Handler,
+};
+
+// Bullets within this radius, about to hit within DODGE_SECONDS, get dodged
+const DODGE_RADIUS: f32 = 300.0;
+const DODGE_SECONDS: f32 = 2.0;
+
+// How close the bot gets to its target before firing at it
+const CHASE_DISTANCE: f32 = 400.0;
+
+/// A bot played by the server itself, inside the game loop of a room, so that
+/// players alone in a room have something to fight. It dodges the bullets
+/// flying at it, and otherwise chases the closest player and fires at them.
+#[derive(Debug)]
+pub struct HouseBot {
+    analyzer: Analyzer,
+    dodge: Dodge,
+    attack: Sequence,
+}
+
+impl Default for HouseBot {
+    fn default() -> Self {
+        Self {
+            analyzer: Analyzer::default(),
+            dodge: Dodge::new(DODGE_RADIUS, DODGE_SECONDS),
+            attack: Sequence::new(),
+        }
+    }
+}
+
+impl Handler for HouseBot {
+    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
+        self.analyzer.push_state(state);
+
+        if let Some(command) = self.dodge.next_command(&self.analyzer) {
+            return Some(command);
+        }
+        if let Some(command) = self.attack.next_command(&self.analyzer) {
+            return Some(command);
+        }
+
+        // Go after whoever is the closest by now
+        self.attack = Sequence::with_slice(&[
+            &Chase::new(Target::Closest, CHASE_DISTANCE),
+            &FireAt::with_times(Target::Closest, 2),
+        ]);
+        self.attack.next_command(&self.analyzer)
+    }
+}
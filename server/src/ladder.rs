@@ -0,0 +1,328 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+// Everybody starts from there
+const INITIAL_RATING: f64 = 1500.0;
+
+// The most a player's rating moves after a match
+const K_FACTOR: f64 = 32.0;
+
+/// A finished match, as stored in the ladder file, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RatedMatch {
+    pub finished_at: u64, // Unix time, in seconds
+    pub room_id: u32,
+    pub room_name: String,
+    pub players: Vec<RatedPlayer>, // Best first
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RatedPlayer {
+    pub api_key: String,
+    pub name: String,
+    pub rank: u32, // Players with the same rank tied
+    pub score: u32,
+}
+
+/// A line of `GET /ladder`. API keys are secrets, so players only show up by
+/// the name of their last match.
+#[derive(Clone, Debug, Serialize)]
+pub struct LadderEntry {
+    pub rank: u32,
+    pub name: String,
+    pub rating: i32,
+    pub matches: u32,
+    pub wins: u32,
+}
+
+/// How a single match went for a player.
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    pub finished_at: u64,
+    pub room_id: u32,
+    pub room_name: String,
+    pub name: String,
+    pub rank: u32,
+    pub players: u32,
+    pub score: u32,
+    pub rating: i32, // After the match
+    pub change: i32,
+}
+
+#[derive(Debug)]
+struct Rating {
+    name: String,
+    rating: f64,
+    matches: u32,
+    wins: u32,
+    history: Vec<HistoryEntry>,
+}
+
+/// Elo ratings of every API key that played a finished match, computed from
+/// the matches of the ladder file, which new matches get appended to.
+#[derive(Debug)]
+pub struct Ladder {
+    file: File,
+    ratings: HashMap<String, Rating>,
+}
+
+impl Ladder {
+    /// Opens the ladder file, creating it if needed, and rates every match in it.
+    pub fn open(path: &Path) -> io::Result<Ladder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut ladder = Ladder { file, ratings: HashMap::new() };
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(rated_match) => ladder.rate(&rated_match),
+                Err(err) => warn!("Skipping a broken match of the ladder file: {}", err),
+            }
+        }
+
+        Ok(ladder)
+    }
+
+    /// Stores a match and updates the ratings of its players. Matches with
+    /// fewer than two players don't count.
+    pub fn record(&mut self, rated_match: RatedMatch) {
+        if rated_match.players.len() < 2 {
+            return;
+        }
+
+        let line = serde_json::to_string(&rated_match).unwrap();
+        if let Err(err) = writeln!(self.file, "{}", line) {
+            warn!("Failed to store a match in the ladder file: {}", err);
+        }
+        self.rate(&rated_match);
+    }
+
+    /// Plays every pair of players of the match as a game of Elo, each player
+    /// moving by the average of their games.
+    fn rate(&mut self, rated_match: &RatedMatch) {
+        let players = &rated_match.players;
+        let ratings: Vec<f64> = players.iter().map(|player| self.rating(&player.api_key)).collect();
+
+        for (i, player) in players.iter().enumerate() {
+            let mut change = 0.0;
+            for (j, opponent) in players.iter().enumerate().filter(|(j, _)| *j != i) {
+                let expected = 1.0 / (1.0 + 10f64.powf((ratings[j] - ratings[i]) / 400.0));
+                let actual = match player.rank.cmp(&opponent.rank) {
+                    Ordering::Less => 1.0,
+                    Ordering::Equal => 0.5,
+                    Ordering::Greater => 0.0,
+                };
+                change += actual - expected;
+            }
+            change *= K_FACTOR / (players.len() - 1) as f64;
+
+            let rating = self.ratings.entry(player.api_key.clone()).or_insert_with(|| Rating {
+                name: String::new(),
+                rating: INITIAL_RATING,
+                matches: 0,
+                wins: 0,
+                history: vec![],
+            });
+            rating.name = player.name.clone();
+            rating.rating = ratings[i] + change;
+            rating.matches += 1;
+            if player.rank == 1 {
+                rating.wins += 1;
+            }
+            rating.history.push(HistoryEntry {
+                finished_at: rated_match.finished_at,
+                room_id: rated_match.room_id,
+                room_name: rated_match.room_name.clone(),
+                name: player.name.clone(),
+                rank: player.rank,
+                players: players.len() as u32,
+                score: player.score,
+                rating: rating.rating.round() as i32,
+                change: change.round() as i32,
+            });
+        }
+    }
+
+    /// Every rated player, the best first.
+    pub fn standings(&self) -> Vec<LadderEntry> {
+        let mut ratings: Vec<&Rating> = self.ratings.values().collect();
+        ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating).then_with(|| a.name.cmp(&b.name)));
+
+        ratings
+            .into_iter()
+            .enumerate()
+            .map(|(i, rating)| LadderEntry {
+                rank: i as u32 + 1,
+                name: rating.name.clone(),
+                rating: rating.rating.round() as i32,
+                matches: rating.matches,
+                wins: rating.wins,
+            })
+            .collect()
+    }
+
+    /// The rating of an API key, the initial one if it never played a match.
+    pub fn rating(&self, api_key: &str) -> f64 {
+        self.ratings.get(api_key).map_or(INITIAL_RATING, |rating| rating.rating)
+    }
+
+    /// The matches played with an API key, the oldest first.
+    pub fn history(&self, api_key: &str) -> Option<Vec<HistoryEntry>> {
+        self.ratings.get(api_key).map(|rating| rating.history.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A ladder file of its own for each test, removed once dropped.
+    struct LadderFile(PathBuf);
+
+    impl LadderFile {
+        fn new(name: &str) -> LadderFile {
+            let path = std::env::temp_dir().join(format!(
+                "tokyo-ladder-{}-{}.jsonl",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            LadderFile(path)
+        }
+
+        fn open(&self) -> Ladder {
+            Ladder::open(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for LadderFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// A match between the given (API key, rank) players.
+    fn rated_match(room_id: u32, players: &[(&str, u32)]) -> RatedMatch {
+        RatedMatch {
+            finished_at: 1000 + u64::from(room_id),
+            room_id,
+            room_name: format!("room {}", room_id),
+            players: players
+                .iter()
+                .map(|&(api_key, rank)| RatedPlayer {
+                    api_key: api_key.to_string(),
+                    name: api_key.to_uppercase(),
+                    rank,
+                    score: 10 - rank,
+                })
+                .collect(),
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn changes_are_symmetric() {
+        let file = LadderFile::new("symmetric");
+        let mut ladder = file.open();
+
+        // Even players trade half the K factor
+        ladder.record(rated_match(1, &[("a", 1), ("b", 2)]));
+        assert_close(ladder.rating("a"), INITIAL_RATING + K_FACTOR / 2.0);
+        assert_close(ladder.rating("b"), INITIAL_RATING - K_FACTOR / 2.0);
+
+        // Whoever wins, what one gains the other loses
+        for (room_id, players) in [(2, [("a", 1), ("b", 2)]), (3, [("b", 1), ("a", 2)])] {
+            let before = ladder.rating("a") + ladder.rating("b");
+            ladder.record(rated_match(room_id, &players));
+            assert_close(ladder.rating("a") + ladder.rating("b"), before);
+        }
+
+        // The favourite gains less by winning than the underdog would have
+        assert!(ladder.rating("a") > ladder.rating("b"));
+        let favourite_gain = ladder.rating("a");
+        ladder.record(rated_match(4, &[("a", 1), ("b", 2)]));
+        let favourite_gain = ladder.rating("a") - favourite_gain;
+        let underdog_gain = ladder.rating("b");
+        ladder.record(rated_match(5, &[("b", 1), ("a", 2)]));
+        let underdog_gain = ladder.rating("b") - underdog_gain;
+        assert!(favourite_gain < K_FACTOR / 2.0 && underdog_gain > K_FACTOR / 2.0);
+    }
+
+    #[test]
+    fn draws_pull_ratings_together() {
+        let file = LadderFile::new("draws");
+        let mut ladder = file.open();
+
+        ladder.record(rated_match(1, &[("a", 1), ("b", 1)]));
+        assert_close(ladder.rating("a"), INITIAL_RATING);
+        assert_close(ladder.rating("b"), INITIAL_RATING);
+
+        ladder.record(rated_match(2, &[("a", 1), ("b", 2)]));
+        let gap = ladder.rating("a") - ladder.rating("b");
+        ladder.record(rated_match(3, &[("b", 1), ("a", 1)]));
+        assert!(ladder.rating("a") - ladder.rating("b") < gap);
+        assert_close(ladder.rating("a") + ladder.rating("b"), 2.0 * INITIAL_RATING);
+
+        // Tied players both count as winners
+        let wins: Vec<(String, u32)> =
+            ladder.standings().into_iter().map(|entry| (entry.name, entry.wins)).collect();
+        assert_eq!(wins, vec![("A".to_string(), 3), ("B".to_string(), 2)]);
+    }
+
+    #[test]
+    fn matches_need_two_players() {
+        let file = LadderFile::new("alone");
+        let mut ladder = file.open();
+
+        ladder.record(rated_match(1, &[("a", 1)]));
+        assert!(ladder.standings().is_empty());
+        assert!(ladder.history("a").is_none());
+        assert!(file.open().standings().is_empty());
+    }
+
+    #[test]
+    fn reloads_the_same_ratings() {
+        let file = LadderFile::new("reload");
+        let mut ladder = file.open();
+        ladder.record(rated_match(1, &[("a", 1), ("b", 2), ("c", 3)]));
+        ladder.record(rated_match(2, &[("c", 1), ("b", 1), ("a", 3)]));
+        ladder.record(rated_match(3, &[("b", 1), ("d", 2)]));
+        let standings = |ladder: &Ladder| {
+            let entries = ladder.standings().into_iter();
+            entries.map(|entry| (entry.name, entry.rating, entry.matches)).collect::<Vec<_>>()
+        };
+        let expected = standings(&ladder);
+        drop(ladder);
+
+        // Broken lines are skipped
+        let mut ladder_file = OpenOptions::new().append(true).open(&file.0).unwrap();
+        writeln!(ladder_file, "{{\"not\": \"a match\"}}").unwrap();
+
+        let mut reloaded = file.open();
+        assert_eq!(standings(&reloaded), expected);
+
+        let history = reloaded.history("b").unwrap();
+        let rooms: Vec<u32> = history.iter().map(|entry| entry.room_id).collect();
+        assert_eq!(rooms, vec![1, 2, 3]);
+        assert_eq!(history[0].players, 3);
+        assert_eq!(
+            (history[2].name.as_str(), history[2].rating),
+            (expected[0].0.as_str(), expected[0].1)
+        );
+
+        // And keeps appending to the same file
+        reloaded.record(rated_match(4, &[("d", 1), ("a", 2)]));
+        let ratings = |ladder: &Ladder| {
+            ["a", "b", "c", "d"].iter().map(|key| ladder.rating(key)).collect::<Vec<_>>()
+        };
+        assert_eq!(ratings(&file.open()), ratings(&reloaded));
+    }
+}
@@ -1,5 +1,3 @@
-#![feature(extract_if)]
-
 #[macro_use]
 extern crate log;
 
@@ -8,28 +6,50 @@ extern crate serde_derive;
 
 mod actors;
 mod controllers;
-mod game;
+mod house_bot;
+mod ladder;
+mod map;
 mod models;
-
-use crate::actors::{GameActor, RoomManagerActor};
+mod queue;
+mod recording;
+mod tournament;
+mod wasm_bot;
+
+use crate::{
+    actors::{LadderActor, RoomManagerActor},
+    ladder::Ladder,
+    queue::QueueConfig,
+};
 use actix::{Actor, Addr, System};
 use actix_web::{http::Method, middleware::Logger, server, App};
 use lazy_static::lazy_static;
 use listenfd::ListenFd;
-use std::collections::HashSet;
-use tokyo::models::GameConfig;
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+use tokyo::models::{GameConfig, Obstacle};
 
 #[derive(Deserialize, Debug)]
 pub struct AppConfig {
     server_port: Option<u16>,
     api_keys: HashSet<String>,
     dev_mode: bool,
+    // Required to create rooms and by the admin endpoints, which are disabled when it's not set
+    admin_key: Option<String>,
+    // Rooms record their matches here when set
+    recordings_dir: Option<PathBuf>,
+    // Rooms idle or finished for this long are closed, they're kept forever if not set
+    room_expiry_seconds: Option<u64>,
+    // Finished matches are rated and kept in this file when set, for the ladder
+    ladder_file: Option<PathBuf>,
+    // Bots can wait in the matchmaking queue for a room when set
+    queue: Option<QueueConfig>,
+    // Rooms can be created with the maps of this directory when set
+    maps_dir: Option<PathBuf>,
     game_config: GameConfig,
 }
 
 pub struct AppState {
-    game_addr: Addr<GameActor>,
     room_manager_addr: Addr<RoomManagerActor>,
+    ladder_addr: Option<Addr<LadderActor>>,
 }
 
 const CONFIG_FILE_PATH: &str = "tokyo.toml";
@@ -48,20 +68,53 @@ fn main() -> Result<(), String> {
     lazy_static::initialize(&APP_CONFIG);
     env_logger::init();
 
+    APP_CONFIG
+        .game_config
+        .rules
+        .validate()
+        .map_err(|err| format!("Invalid game rules: {}", err))?;
+    APP_CONFIG
+        .game_config
+        .obstacles
+        .iter()
+        .try_for_each(Obstacle::validate)
+        .map_err(|err| format!("Invalid obstacle: {}", err))?;
+
+    if APP_CONFIG.admin_key.as_deref().unwrap_or_default().is_empty() {
+        println!("No admin_key is set in {}, the admin endpoints are disabled", CONFIG_FILE_PATH);
+    }
+
     let server_port = APP_CONFIG.server_port.unwrap_or(3000);
 
     let actor_system = System::new("meetup-server");
 
-    let game_actor = GameActor::new(APP_CONFIG.game_config, 0, 0);
-    let game_actor_addr = game_actor.start();
+    if let Some(recordings_dir) = &APP_CONFIG.recordings_dir {
+        std::fs::create_dir_all(recordings_dir)
+            .map_err(|err| format!("Failed to create recordings dir: {}", err))?;
+    }
+
+    let ladder_addr = match &APP_CONFIG.ladder_file {
+        Some(ladder_file) => {
+            let ladder = Ladder::open(ladder_file)
+                .map_err(|err| format!("Failed to open ladder file: {}", err))?;
+            Some(LadderActor::new(ladder).start())
+        },
+        None => None,
+    };
 
-    let room_manager_actor = actors::RoomManagerActor::new(APP_CONFIG.game_config);
+    let room_manager_actor = actors::RoomManagerActor::new(
+        APP_CONFIG.game_config.clone(),
+        APP_CONFIG.recordings_dir.clone(),
+        APP_CONFIG.room_expiry_seconds.map(Duration::from_secs),
+        ladder_addr.clone(),
+        APP_CONFIG.queue,
+    );
     let room_manager_addr = room_manager_actor.start();
 
     let mut server = server::new(move || {
         let app_state = AppState {
-            game_addr: game_actor_addr.clone(),
             room_manager_addr: room_manager_addr.clone(),
+            ladder_addr: ladder_addr.clone(),
         };
 
         App::with_state(app_state)
@@ -70,15 +123,50 @@ fn main() -> Result<(), String> {
                 r.method(Method::POST).with(controllers::api::create_room_handler);
                 r.method(Method::GET).with(controllers::api::list_rooms_handler);
             })
+            .resource("/rooms/{id}", |r| {
+                r.method(Method::DELETE).with(controllers::api::delete_room_handler);
+            })
+            .resource("/rooms/{id}/admin", |r| {
+                r.method(Method::POST).with(controllers::api::room_admin_handler);
+            })
+            .resource("/rooms/{id}/results", |r| {
+                r.method(Method::GET).with(controllers::api::room_results_handler);
+            })
+            .resource("/replays", |r| {
+                r.method(Method::POST).with(controllers::api::create_replay_handler);
+            })
+            .resource("/replays/{token}/control", |r| {
+                r.method(Method::POST).with(controllers::api::replay_control_handler);
+            })
+            .resource("/bots", |r| {
+                r.method(Method::POST).with_config(
+                    controllers::api::host_bot_handler,
+                    |((_, _, payload),)| {
+                        payload.limit(controllers::api::MAX_BOT_MODULE_BYTES);
+                    },
+                );
+            })
+            .resource("/tournaments", |r| {
+                r.method(Method::POST).with(controllers::api::create_tournament_handler);
+            })
+            .resource("/tournaments/{id}", |r| {
+                r.method(Method::GET).with(controllers::api::tournament_handler);
+            })
+            .resource("/ladder", |r| {
+                r.method(Method::GET).with(controllers::api::ladder_handler);
+            })
+            .resource("/ladder/history", |r| {
+                r.method(Method::GET).with(controllers::api::ladder_history_handler);
+            })
             .resource("/socket", |r| {
                 r.method(Method::GET).with(controllers::api::socket_handler);
             })
+            .resource("/queue", |r| {
+                r.method(Method::GET).with(controllers::api::queue_handler);
+            })
             .resource("/spectate", |r| {
                 r.method(Method::GET).with(controllers::api::spectate_handler);
             })
-            .resource("/reset", |r| {
-                r.method(Method::GET).with(controllers::api::reset_handler);
-            })
             .handler(
                 "/",
                 actix_web::fs::StaticFiles::new("./spectator/").unwrap().index_file("index.html"),
@@ -0,0 +1,56 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+use tokyo::models::{GameConfig, Obstacle};
+
+pub const MAP_FILE_EXTENSION: &str = "json";
+
+/// The layout of an arena, loaded from a JSON file of the `maps_dir`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GameMap {
+    // The size of the arena, that of the config is kept if not set
+    #[serde(default)]
+    pub bounds: Option<(f32, f32)>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+}
+
+impl GameMap {
+    /// Reads the map called `name`, from the `{name}.json` file of `dir`.
+    pub fn load(dir: &Path, name: &str) -> io::Result<GameMap> {
+        // Maps are named after their file, which must stay inside the directory
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid map name"));
+        }
+
+        let path = dir.join(format!("{}.{}", name, MAP_FILE_EXTENSION));
+        let map: GameMap = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        map.validate().map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        Ok(map)
+    }
+
+    /// Checks that the bounds and every obstacle of the map make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some((bound_x, bound_y)) = self.bounds {
+            if !(bound_x.is_finite() && bound_y.is_finite() && bound_x > 0.0 && bound_y > 0.0) {
+                return Err("The bounds of a map must be positive".to_string());
+            }
+        }
+
+        self.obstacles.iter().try_for_each(Obstacle::validate)
+    }
+
+    /// Lays the map out over the arena of `config`.
+    pub fn apply_to(self, config: &mut GameConfig) {
+        if let Some((bound_x, bound_y)) = self.bounds {
+            config.bound_x = bound_x;
+            config.bound_y = bound_y;
+        }
+        config.obstacles = self.obstacles;
+    }
+}
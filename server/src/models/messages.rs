@@ -1,16 +1,158 @@
-use actix::Message;
-use tokyo::models::GameCommand;
+use crate::{
+    actors::{client_ws_actor::EncodedUpdate, ClientWsActor, LadderActor},
+    wasm_bot::WasmBot,
+};
+use actix::{Addr, Message};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use tokyo::models::{
+    CommandError, GameCommand, GameState, GameStateDelta, MatchResults, PlayerStats, Team,
+};
 
 #[derive(Debug, Message)]
 pub struct PlayerGameCommand {
     pub api_key: String,
     pub cmd: GameCommand,
+    pub addr: Addr<ClientWsActor>, // Told when the command can't be played
 }
 
+/// The outcome of a command sent by a player: the game tick at which it was
+/// applied, or why it had no effect.
 #[derive(Debug, Message)]
-pub struct ClientStop {}
+pub struct CommandResult {
+    pub player_id: u32,
+    pub result: Result<u64, CommandError>,
+}
+
+#[derive(Debug, Message)]
+pub struct ClientStop {
+    pub reason: String, // Sent to the client as the close reason of the socket
+}
+
+/// Shuts a room down: its game loop stops and every client is disconnected
+/// with the given reason.
+#[derive(Debug, Message)]
+pub struct CloseRoom {
+    pub reason: String,
+}
+
+/// Adds bots played by the server to a room.
+#[derive(Debug, Message)]
+pub struct AddHouseBots {
+    pub count: u32,
+}
+
+/// Has the server play a bot uploaded as a WebAssembly module, for the player
+/// with the given API key. Returns their player ID.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<u32, String>")]
+pub struct HostBot {
+    pub api_key: String,
+    pub team_name: String,
+    pub bot: Box<WasmBot>, // Already compiled, so the game loop doesn't stall on it
+}
+
+/// A team of a room, and the API keys of its players.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RoomTeam {
+    pub name: String,
+    pub api_keys: Vec<String>,
+}
+
+/// Turns the game of a room into a team game. Players join the team of their
+/// API key.
+#[derive(Debug, Message)]
+pub struct SetTeams(pub Vec<RoomTeam>);
+
+/// Has a room send the results of its matches to the ladder once they're over.
+#[derive(Debug, Message)]
+pub struct RateMatches {
+    pub ladder: Addr<LadderActor>,
+    pub room_id: u32,
+    pub room_name: String,
+}
+
+/// Asks a room since when it has been idle or finished, if it is.
+#[derive(Debug, Message)]
+#[rtype(result = "Option<Instant>")]
+pub struct GetInactiveSince;
 
+/// The final scores of a match, sent by the game loop once it's over.
 #[derive(Debug, Message)]
+pub struct MatchOver {
+    pub scoreboard: HashMap<u32, u32>,
+    pub stats: HashMap<u32, PlayerStats>,
+    pub left_players: HashMap<u32, u32>, // Player ID -> score when they left
+    pub teams: Vec<Team>,
+}
+
+/// Asks a room for the results of its last finished match.
+#[derive(Debug, Message)]
+#[rtype(result = "Option<MatchResults>")]
+pub struct GetResults;
+
+/// An admin operation on a room.
+#[derive(Debug, Deserialize, Message)]
+#[rtype(result = "Result<(), String>")]
+#[serde(tag = "e", content = "data")]
 pub enum ServerCommand {
-    Reset
+    #[serde(rename = "reset")]
+    Reset,
+
+    #[serde(rename = "start")]
+    Start, // Starts the countdown of a match in the lobby, even if not everybody is ready.
+
+    #[serde(rename = "pause")]
+    Pause,
+
+    #[serde(rename = "resume")]
+    Resume,
+
+    #[serde(rename = "end")]
+    EndMatch, // Ends the match now, as if the time limit was reached.
+
+    #[serde(rename = "time_limit")]
+    SetTimeLimit(u32), // In seconds, counted from the start of the match.
+
+    #[serde(rename = "kick")]
+    Kick(Player),
+}
+
+#[derive(Debug, Deserialize)]
+pub enum Player {
+    #[serde(rename = "player_id")]
+    Id(u32),
+
+    #[serde(rename = "api_key")]
+    ApiKey(String),
+}
+
+/// The game state after a tick, shared by every connection of the room.
+#[derive(Clone, Debug, Message)]
+pub struct StateUpdate {
+    pub seq: u64,
+    pub state: Arc<GameState>,
+    pub delta: Option<Arc<GameStateDelta>>, // None when it's time for a keyframe
+    pub encoded: Arc<EncodedUpdate>,        // Filled in by the connections as they need it
+}
+
+#[derive(Debug, Message)]
+pub struct TeamNames(pub HashMap<u32, String>);
+
+#[derive(Debug, Deserialize, Message)]
+#[serde(tag = "e", content = "data")]
+pub enum ReplayControl {
+    #[serde(rename = "speed")]
+    Speed(f32), // Playback speed, 1.0 being real time.
+
+    #[serde(rename = "pause")]
+    Pause,
+
+    #[serde(rename = "resume")]
+    Resume,
+
+    #[serde(rename = "step")]
+    Step, // Advance a paused replay by a single tick.
+
+    #[serde(skip)] // Sent by the room, when somebody starts or stops receiving deltas
+    SendDeltas(bool),
 }
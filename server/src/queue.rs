@@ -0,0 +1,89 @@
+use crate::actors::QueueWsActor;
+use actix::Addr;
+use std::collections::HashMap;
+
+/// How the matchmaking queue fills rooms, from the `[queue]` table of the
+/// config.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct QueueConfig {
+    pub players_per_match: u32,
+    pub time_limit_seconds: u32,
+}
+
+/// A bot waiting in the queue, connected to the `/queue` socket.
+pub struct QueuedPlayer {
+    pub api_key: String,
+    pub name: String,
+    pub addr: Addr<QueueWsActor>,
+}
+
+/// The bots waiting for a match, in the order they joined. Knows nothing about
+/// rooms: `RoomManagerActor` creates one for every group it hands out.
+#[derive(Default)]
+pub struct MatchQueue {
+    players: Vec<QueuedPlayer>,
+}
+
+impl MatchQueue {
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn api_keys(&self) -> Vec<String> {
+        self.players.iter().map(|player| player.api_key.clone()).collect()
+    }
+
+    /// Adds a bot at the back of the queue, unless its API key is already
+    /// waiting.
+    pub fn join(&mut self, player: QueuedPlayer) -> Result<(), String> {
+        if self.players.iter().any(|queued| queued.api_key == player.api_key) {
+            return Err("This API key is already waiting in the queue".to_string());
+        }
+        self.players.push(player);
+        Ok(())
+    }
+
+    /// Removes the bot behind a socket, if it's still waiting.
+    pub fn leave(&mut self, addr: &Addr<QueueWsActor>) {
+        self.players.retain(|player| player.addr != *addr);
+    }
+
+    /// Takes the next group of `size` bots out of the queue, if enough are
+    /// waiting. The bot that waited the longest always plays, along with the
+    /// bots closest to its rating, `ratings` being by API key. Without
+    /// ratings, that's simply the first bots in line.
+    pub fn next_match(
+        &mut self,
+        size: usize,
+        ratings: &HashMap<String, f64>,
+    ) -> Option<Vec<QueuedPlayer>> {
+        if size == 0 || self.players.len() < size {
+            return None;
+        }
+
+        let rating = |i: usize| ratings.get(&self.players[i].api_key).copied().unwrap_or_default();
+        // A stable sort keeps bots with the same rating in line
+        let mut by_rating: Vec<usize> = (0..self.players.len()).collect();
+        by_rating.sort_by(|&a, &b| rating(a).total_cmp(&rating(b)));
+
+        // Of the windows of `size` bots around the longest waiting one, the
+        // one with the smallest spread of ratings
+        let oldest = by_rating.iter().position(|&i| i == 0).unwrap();
+        let first = oldest.saturating_sub(size - 1);
+        let last = oldest.min(by_rating.len() - size);
+        let start = (first..=last)
+            .min_by(|&a, &b| {
+                let spread =
+                    |start: usize| rating(by_rating[start + size - 1]) - rating(by_rating[start]);
+                spread(a).total_cmp(&spread(b))
+            })
+            .unwrap();
+
+        let mut group: Vec<usize> = by_rating[start..start + size].to_vec();
+        group.sort_unstable_by(|a, b| b.cmp(a));
+        let mut players: Vec<QueuedPlayer> =
+            group.into_iter().map(|i| self.players.remove(i)).collect();
+        players.reverse();
+        Some(players)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::actors::game_actor::GameLoopCommand;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+use tokyo::models::{GameConfig, GameState};
+
+/// Bumped whenever the layout of a match file changes.
+pub const MATCH_FILE_VERSION: u32 = 1;
+
+pub const MATCH_FILE_EXTENSION: &str = "match";
+
+/// How often a full `GameState` is written next to the command log.
+pub const KEYFRAME_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Everything needed to rebuild a `Game` before replaying its commands.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MatchHeader {
+    pub version: u32,
+    pub config: GameConfig,
+    pub max_players: u32,
+    pub time_limit_seconds: u32,
+}
+
+/// A single entry of a match file. Entries are stored in the order they
+/// happened, each tagged with the game tick it was applied on.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum RecordEntry {
+    Command(u64, GameLoopCommand),
+    Keyframe(u64, Box<GameState>),
+    TeamNames(u64, HashMap<u32, String>),
+    End(u64),
+}
+
+impl RecordEntry {
+    pub fn tick(&self) -> u64 {
+        match self {
+            RecordEntry::Command(tick, _)
+            | RecordEntry::Keyframe(tick, _)
+            | RecordEntry::TeamNames(tick, _)
+            | RecordEntry::End(tick) => *tick,
+        }
+    }
+}
+
+/// Appends the entries of a running match to a MessagePack encoded match file.
+pub struct MatchRecorder {
+    writer: BufWriter<File>,
+}
+
+impl MatchRecorder {
+    pub fn create(path: &Path, header: &MatchHeader) -> io::Result<MatchRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        rmp_serde::encode::write_named(&mut writer, header).map_err(to_io_error)?;
+
+        Ok(MatchRecorder { writer })
+    }
+
+    pub fn record(&mut self, entry: &RecordEntry) {
+        if let Err(err) = rmp_serde::encode::write_named(&mut self.writer, entry) {
+            warn!("Failed to record match entry: {}", err);
+        }
+
+        // Keep the file readable while the match is still going
+        if let RecordEntry::Keyframe(..) | RecordEntry::End(_) = entry {
+            if let Err(err) = self.writer.flush() {
+                warn!("Failed to flush match file: {}", err);
+            }
+        }
+    }
+}
+
+/// A match file loaded in memory, ready to be replayed.
+#[derive(Debug)]
+pub struct MatchRecording {
+    pub header: MatchHeader,
+    pub entries: Vec<RecordEntry>,
+}
+
+impl MatchRecording {
+    pub fn load(path: &Path) -> io::Result<MatchRecording> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header: MatchHeader = rmp_serde::decode::from_read(&mut reader).map_err(to_io_error)?;
+
+        if header.version != MATCH_FILE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported match file version {}", header.version),
+            ));
+        }
+
+        let mut entries = vec![];
+        loop {
+            match rmp_serde::decode::from_read(&mut reader) {
+                Ok(entry) => entries.push(entry),
+                // A match that was cut short simply ends at its last entry
+                Err(rmp_serde::decode::Error::InvalidMarkerRead(ref err))
+                    if err.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    break
+                },
+                Err(err) => return Err(to_io_error(err)),
+            }
+        }
+
+        Ok(MatchRecording { header, entries })
+    }
+}
+
+fn to_io_error(err: impl std::error::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
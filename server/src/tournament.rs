@@ -0,0 +1,642 @@
+use std::collections::HashSet;
+use tokyo::models::{GameRules, MatchResults};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    RoundRobin,        // Everybody plays everybody once
+    Swiss,             // Players with the same points play each other, for a few rounds
+    SingleElimination, // Losers are out
+}
+
+/// A player registered in a tournament. Their API key is the only one allowed
+/// in the rooms of their matches, where they're shown with their name.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Entrant {
+    pub key: String,
+    pub name: String,
+}
+
+/// The room a match is played in. Only its players can join it, so its token
+/// isn't a secret.
+#[derive(Clone, Debug)]
+pub struct MatchRoom {
+    pub id: u32,
+    pub token: String,
+}
+
+#[derive(Debug)]
+struct Match {
+    players: Vec<usize>, // Indices into the entrants, a single one for a bye
+    room: Option<MatchRoom>,
+    outcome: Option<Outcome>,
+}
+
+#[derive(Debug)]
+struct Outcome {
+    scores: Vec<u32>,      // In the same order as the players of the match
+    winner: Option<usize>, // None for a draw
+}
+
+/// Pairs registered players up, round after round, into matches played in
+/// rooms of their own, and ranks them by the outcome of their matches. Knows
+/// nothing about rooms besides their ID and token: `RoomManagerActor` creates
+/// them and reports their results.
+#[derive(Debug)]
+pub struct Tournament {
+    pub id: u32,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub time_limit_seconds: u32,
+    pub rules: Option<GameRules>, // Of the rooms, the rules of the server's config are used if not set
+    entrants: Vec<Entrant>,       // In seeding order
+    num_rounds: usize,
+    rounds: Vec<Vec<Match>>,
+}
+
+impl Tournament {
+    /// Validates the entrants and schedules the first round. Swiss tournaments
+    /// play `swiss_rounds` rounds, enough to single out a winner by default.
+    pub fn new(
+        id: u32,
+        name: String,
+        format: TournamentFormat,
+        entrants: Vec<Entrant>,
+        time_limit_seconds: u32,
+        swiss_rounds: Option<u32>,
+    ) -> Result<Tournament, String> {
+        if entrants.len() < 2 {
+            return Err("A tournament needs at least 2 players".to_string());
+        }
+        let keys: HashSet<&str> = entrants.iter().map(|entrant| entrant.key.as_str()).collect();
+        let names: HashSet<&str> = entrants.iter().map(|entrant| entrant.name.as_str()).collect();
+        if keys.len() != entrants.len() || names.len() != entrants.len() {
+            return Err("Every player needs their own key and name".to_string());
+        }
+        if time_limit_seconds == 0 {
+            return Err("Tournament matches need a time limit".to_string());
+        }
+
+        let n = entrants.len();
+        let num_rounds = match format {
+            TournamentFormat::RoundRobin => n + n % 2 - 1,
+            TournamentFormat::Swiss => {
+                swiss_rounds.map_or(log2_ceil(n), |rounds| rounds as usize).clamp(1, n + n % 2 - 1)
+            },
+            TournamentFormat::SingleElimination => log2_ceil(n),
+        };
+
+        let mut tournament = Tournament {
+            id,
+            name,
+            format,
+            time_limit_seconds,
+            rules: None,
+            entrants,
+            num_rounds,
+            rounds: vec![],
+        };
+        tournament.schedule_next_round();
+        Ok(tournament)
+    }
+
+    pub fn entrant(&self, index: usize) -> &Entrant {
+        &self.entrants[index]
+    }
+
+    /// The current round, starting from 1.
+    pub fn round(&self) -> usize {
+        self.rounds.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rounds.len() == self.num_rounds && self.is_round_over()
+    }
+
+    fn is_round_over(&self) -> bool {
+        self.rounds.last().is_none_or(|round| round.iter().all(|m| m.outcome.is_some()))
+    }
+
+    /// The matches of the current round still waiting for a room, as their
+    /// index in the round along with their players.
+    pub fn matches_without_room(&self) -> Vec<(usize, Vec<usize>)> {
+        let round = self.rounds.last().map(Vec::as_slice).unwrap_or_default();
+        round
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.room.is_none() && m.outcome.is_none())
+            .map(|(i, m)| (i, m.players.clone()))
+            .collect()
+    }
+
+    pub fn set_room(&mut self, match_index: usize, room: MatchRoom) {
+        if let Some(m) = self.rounds.last_mut().and_then(|round| round.get_mut(match_index)) {
+            m.room = Some(room);
+        }
+    }
+
+    /// The rooms of the matches of the current round still being played.
+    pub fn rooms_in_play(&self) -> Vec<MatchRoom> {
+        let round = self.rounds.last().map(Vec::as_slice).unwrap_or_default();
+        round.iter().filter(|m| m.outcome.is_none()).filter_map(|m| m.room.clone()).collect()
+    }
+
+    /// Records the results of the match played in a room, if it belongs to the
+    /// current round. Players are found by name, which their key is bound to.
+    /// The ones who never showed up are ranked last. Schedules the next round
+    /// once the current one is over, returning whether it did.
+    pub fn record(&mut self, room_id: u32, results: &MatchResults) -> bool {
+        let format = self.format;
+        let entrants = &self.entrants;
+        let m = match self.rounds.last_mut().and_then(|round| {
+            round.iter_mut().find(|m| m.room.as_ref().is_some_and(|room| room.id == room_id))
+        }) {
+            Some(m) if m.outcome.is_none() => m,
+            _ => return false,
+        };
+
+        let standing = |player: usize| {
+            results.standings.iter().find(|standing| standing.name == entrants[player].name)
+        };
+        let scores = m.players.iter().map(|&p| standing(p).map_or(0, |s| s.score)).collect();
+        let mut order: Vec<(usize, u32, usize)> = m
+            .players
+            .iter()
+            .map(|&p| {
+                let position = results.standings.iter().position(|s| s.name == entrants[p].name);
+                let rank = standing(p).map_or(u32::MAX, |s| s.rank);
+                (p, rank, position.unwrap_or(usize::MAX))
+            })
+            .collect();
+        // Ties are broken like the standings of the room, then by seed
+        order.sort_by_key(|&(p, rank, position)| (rank, position, p));
+
+        let draw = order[0].1 == order[1].1 && format != TournamentFormat::SingleElimination;
+        let winner = if draw { None } else { Some(order[0].0) };
+        m.outcome = Some(Outcome { scores, winner });
+
+        self.schedule_next_round()
+    }
+
+    /// Pairs the players up for the next round, once the current one is over.
+    /// Byes are won right away.
+    fn schedule_next_round(&mut self) -> bool {
+        if !self.is_round_over() || self.rounds.len() == self.num_rounds {
+            return false;
+        }
+
+        let pairings = match self.format {
+            TournamentFormat::RoundRobin => self.round_robin_pairings(),
+            TournamentFormat::Swiss => self.swiss_pairings(),
+            TournamentFormat::SingleElimination => self.elimination_pairings(),
+        };
+        let round = pairings
+            .into_iter()
+            .map(|players| {
+                let outcome = match players.len() {
+                    1 => Some(Outcome { scores: vec![0], winner: Some(players[0]) }),
+                    _ => None,
+                };
+                Match { players, room: None, outcome }
+            })
+            .collect();
+        self.rounds.push(round);
+        true
+    }
+
+    /// The circle method: the first player stays put while the others turn
+    /// around them, a bye taking the empty seat when they're odd.
+    fn round_robin_pairings(&self) -> Vec<Vec<usize>> {
+        let n = self.entrants.len();
+        let seats = n + n % 2;
+        let round = self.rounds.len();
+
+        let mut circle: Vec<Option<usize>> =
+            (0..seats).map(|i| Some(i).filter(|&i| i < n)).collect();
+        circle[1..].rotate_right(round);
+
+        (0..seats / 2)
+            .map(|i| [circle[i], circle[seats - 1 - i]].iter().flatten().copied().collect())
+            .collect()
+    }
+
+    /// Players are paired from the top of the standings down, each with the
+    /// best one they haven't played yet if any. The lowest one without a bye
+    /// gets one when they're odd.
+    fn swiss_pairings(&self) -> Vec<Vec<usize>> {
+        let mut unpaired: Vec<usize> = self.standings().into_iter().map(|s| s.player).collect();
+
+        let mut bye = None;
+        if unpaired.len() % 2 == 1 {
+            let had_bye = |p: usize| self.matches().any(|m| m.players == [p]);
+            let index = unpaired.iter().rposition(|&p| !had_bye(p)).unwrap_or(unpaired.len() - 1);
+            bye = Some(vec![unpaired.remove(index)]);
+        }
+
+        // Rematches only when there's no way around them
+        let mut pairings = self
+            .pair_without_rematches(&unpaired)
+            .unwrap_or_else(|| unpaired.chunks(2).map(<[usize]>::to_vec).collect());
+        pairings.extend(bye);
+        pairings
+    }
+
+    /// Pairs each player, best placed first, with the best placed opponent
+    /// they haven't played yet, backing off when that leaves the others
+    /// without one.
+    fn pair_without_rematches(&self, players: &[usize]) -> Option<Vec<Vec<usize>>> {
+        let (&player, others) = match players.split_first() {
+            Some(split) => split,
+            None => return Some(vec![]),
+        };
+        let played = |opponent: usize| {
+            self.matches().any(|m| m.players.contains(&player) && m.players.contains(&opponent))
+        };
+
+        others.iter().enumerate().filter(|&(_, &opponent)| !played(opponent)).find_map(
+            |(i, &opponent)| {
+                let rest: Vec<usize> = [&others[..i], &others[i + 1..]].concat();
+                let mut pairings = self.pair_without_rematches(&rest)?;
+                pairings.insert(0, vec![player, opponent]);
+                Some(pairings)
+            },
+        )
+    }
+
+    /// The first round follows the usual bracket, where the best seeds only
+    /// meet late and get the byes. The winners of two neighbouring matches
+    /// meet in the next round.
+    fn elimination_pairings(&self) -> Vec<Vec<usize>> {
+        let n = self.entrants.len();
+        match self.rounds.last() {
+            None => bracket_order(1 << log2_ceil(n))
+                .chunks(2)
+                .map(|pair| pair.iter().copied().filter(|&p| p < n).collect())
+                .collect(),
+            Some(round) => {
+                let winners: Vec<usize> =
+                    round.iter().filter_map(|m| m.outcome.as_ref()?.winner).collect();
+                winners.chunks(2).map(<[usize]>::to_vec).collect()
+            },
+        }
+    }
+
+    fn matches(&self) -> impl Iterator<Item = &Match> {
+        self.rounds.iter().flatten()
+    }
+
+    /// Ranks the players by points: 1 for a win or a bye, 0.5 for a draw. Ties
+    /// are broken by the score of their matches, then by seed.
+    pub fn standings(&self) -> Vec<TournamentStanding> {
+        let mut standings: Vec<TournamentStanding> = (0..self.entrants.len())
+            .map(|player| TournamentStanding { player, points: 0.0, score: 0, matches: 0 })
+            .collect();
+
+        for m in self.matches() {
+            let outcome = match &m.outcome {
+                Some(outcome) => outcome,
+                None => continue,
+            };
+            for (&player, &score) in m.players.iter().zip(&outcome.scores) {
+                let standing = &mut standings[player];
+                standing.matches += 1;
+                standing.score += score;
+                standing.points += match outcome.winner {
+                    Some(winner) if winner == player => 1.0,
+                    Some(_) => 0.0,
+                    None => 0.5,
+                };
+            }
+        }
+
+        standings.sort_by(|a, b| {
+            b.points.total_cmp(&a.points).then(b.score.cmp(&a.score)).then(a.player.cmp(&b.player))
+        });
+        standings
+    }
+
+    /// The progress of the tournament, as served by the REST API.
+    pub fn progress(&self) -> TournamentProgress {
+        let name = |player: usize| self.entrants[player].name.clone();
+        let standings = self.standings();
+
+        let winner = match self.format {
+            _ if !self.is_finished() => None,
+            TournamentFormat::SingleElimination => self
+                .rounds
+                .last()
+                .and_then(|round| round.first())
+                .and_then(|m| m.outcome.as_ref()?.winner)
+                .map(name),
+            _ => standings.first().map(|standing| name(standing.player)),
+        };
+
+        TournamentProgress {
+            id: self.id,
+            name: self.name.clone(),
+            format: self.format,
+            time_limit_seconds: self.time_limit_seconds,
+            rounds: self.num_rounds as u32,
+            finished: self.is_finished(),
+            winner,
+            standings: standings
+                .iter()
+                .enumerate()
+                .map(|(i, standing)| StandingProgress {
+                    rank: i as u32 + 1,
+                    name: name(standing.player),
+                    points: standing.points,
+                    score: standing.score,
+                    matches: standing.matches,
+                })
+                .collect(),
+            schedule: self
+                .rounds
+                .iter()
+                .map(|round| {
+                    round
+                        .iter()
+                        .map(|m| MatchProgress {
+                            players: m.players.iter().copied().map(name).collect(),
+                            room_id: m.room.as_ref().map(|room| room.id),
+                            room_token: m.room.as_ref().map(|room| room.token.clone()),
+                            scores: m.outcome.as_ref().map(|outcome| outcome.scores.clone()),
+                            winner: m.outcome.as_ref().and_then(|outcome| outcome.winner).map(name),
+                            finished: m.outcome.is_some(),
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How a player is doing in a tournament.
+#[derive(Debug)]
+pub struct TournamentStanding {
+    pub player: usize,
+    pub points: f32,
+    pub score: u32,
+    pub matches: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TournamentProgress {
+    pub id: u32,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub time_limit_seconds: u32,
+    pub rounds: u32,
+    pub finished: bool,
+    pub winner: Option<String>,
+    pub standings: Vec<StandingProgress>,
+    pub schedule: Vec<Vec<MatchProgress>>, // The rounds scheduled so far
+}
+
+#[derive(Debug, Serialize)]
+pub struct StandingProgress {
+    pub rank: u32,
+    pub name: String,
+    pub points: f32,
+    pub score: u32,
+    pub matches: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchProgress {
+    pub players: Vec<String>,
+    pub room_id: Option<u32>,
+    pub room_token: Option<String>,
+    pub scores: Option<Vec<u32>>,
+    pub winner: Option<String>, // None until it's over, or for a draw
+    pub finished: bool,
+}
+
+fn log2_ceil(n: usize) -> usize {
+    n.next_power_of_two().trailing_zeros() as usize
+}
+
+/// The seeds of a bracket of `size` players, in the order they're paired up,
+/// e.g. 0, 7, 3, 4, 1, 6, 2, 5 for 8 players.
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let len = order.len() * 2;
+        order = order.iter().flat_map(|&seed| [seed, len - 1 - seed]).collect();
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokyo::models::{PlayerStats, Standing};
+
+    fn new_tournament(format: TournamentFormat, num_players: usize) -> Tournament {
+        let entrants = (0..num_players)
+            .map(|i| Entrant { key: format!("key-{}", i), name: format!("player-{}", i) })
+            .collect();
+        Tournament::new(1, "test".to_string(), format, entrants, 60, None).unwrap()
+    }
+
+    /// The results of a room, from the (name, rank, score) of its players.
+    fn results(standings: &[(&str, u32, u32)]) -> MatchResults {
+        let standings = standings
+            .iter()
+            .enumerate()
+            .map(|(player_id, &(name, rank, score))| Standing {
+                rank,
+                player_id: player_id as u32,
+                name: name.to_string(),
+                score,
+                stats: PlayerStats::default(),
+                left: false,
+            })
+            .collect();
+        MatchResults { standings, teams: vec![] }
+    }
+
+    /// Plays every match of the current round in a room of its own, the
+    /// player picked by `winner` winning it.
+    fn play_round(tournament: &mut Tournament, winner: impl Fn(&[usize]) -> usize) {
+        let round = tournament.round() as u32;
+        for (index, players) in tournament.matches_without_room() {
+            let room_id = round * 100 + index as u32;
+            tournament.set_room(index, MatchRoom { id: room_id, token: room_id.to_string() });
+
+            let winner = winner(&players);
+            let loser = *players.iter().find(|&&p| p != winner).unwrap();
+            let name = |p: usize| tournament.entrant(p).name.clone();
+            let (winner_name, loser_name) = (name(winner), name(loser));
+            tournament.record(room_id, &results(&[(&winner_name, 1, 5), (&loser_name, 2, 1)]));
+        }
+    }
+
+    fn play_out(tournament: &mut Tournament, winner: impl Fn(&[usize]) -> usize) {
+        while !tournament.is_finished() {
+            let round = tournament.round();
+            play_round(tournament, &winner);
+            assert!(tournament.round() > round || tournament.is_finished());
+        }
+    }
+
+    fn lowest_seed(players: &[usize]) -> usize {
+        *players.iter().min().unwrap()
+    }
+
+    /// How many times each pair of players met, and each player had a bye.
+    fn meetings(tournament: &Tournament) -> (HashMap<(usize, usize), u32>, HashMap<usize, u32>) {
+        let mut pairs = HashMap::new();
+        let mut byes = HashMap::new();
+        for m in tournament.matches() {
+            match m.players[..] {
+                [player] => *byes.entry(player).or_default() += 1,
+                [a, b] => *pairs.entry((a.min(b), a.max(b))).or_default() += 1,
+                _ => panic!("A match of {} players", m.players.len()),
+            }
+        }
+        (pairs, byes)
+    }
+
+    #[test]
+    fn round_robin_plays_every_pair_once() {
+        for num_players in 2..=7 {
+            let mut tournament = new_tournament(TournamentFormat::RoundRobin, num_players);
+            play_out(&mut tournament, lowest_seed);
+
+            let (pairs, byes) = meetings(&tournament);
+            assert_eq!(pairs.len(), num_players * (num_players - 1) / 2);
+            assert!(pairs.values().all(|&count| count == 1));
+
+            if num_players % 2 == 1 {
+                assert_eq!(byes.len(), num_players);
+                assert!(byes.values().all(|&count| count == 1));
+            } else {
+                assert!(byes.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_ranks_by_points() {
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 4);
+        play_out(&mut tournament, lowest_seed);
+
+        let standings = tournament.standings();
+        let order: Vec<usize> = standings.iter().map(|standing| standing.player).collect();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+        assert_eq!(standings[0].points, 3.0);
+        assert_eq!(tournament.progress().winner.as_deref(), Some("player-0"));
+    }
+
+    #[test]
+    fn swiss_avoids_rematches() {
+        for num_players in [4, 5, 6, 8] {
+            let mut tournament = new_tournament(TournamentFormat::Swiss, num_players);
+            play_out(&mut tournament, lowest_seed);
+
+            assert_eq!(tournament.round(), log2_ceil(num_players));
+            let (pairs, byes) = meetings(&tournament);
+            assert!(pairs.values().all(|&count| count == 1), "Rematch among {:?}", pairs);
+            assert!(byes.values().all(|&count| count == 1), "Second bye among {:?}", byes);
+        }
+    }
+
+    #[test]
+    fn swiss_pairs_the_leaders() {
+        let mut tournament = new_tournament(TournamentFormat::Swiss, 8);
+        play_round(&mut tournament, lowest_seed);
+
+        // The 4 winners of the first round play each other
+        let winners: Vec<usize> =
+            tournament.standings().iter().filter(|s| s.points == 1.0).map(|s| s.player).collect();
+        for (_, players) in tournament.matches_without_room() {
+            let winners_in_match = players.iter().filter(|p| winners.contains(p)).count();
+            assert!(winners_in_match == 0 || winners_in_match == 2);
+        }
+    }
+
+    #[test]
+    fn bracket_order_keeps_the_best_seeds_apart() {
+        assert_eq!(bracket_order(2), vec![0, 1]);
+        assert_eq!(bracket_order(4), vec![0, 3, 1, 2]);
+        assert_eq!(bracket_order(8), vec![0, 7, 3, 4, 1, 6, 2, 5]);
+    }
+
+    #[test]
+    fn single_elimination_gives_the_byes_to_the_best_seeds() {
+        let mut tournament = new_tournament(TournamentFormat::SingleElimination, 6);
+
+        let first_round: Vec<Vec<usize>> =
+            tournament.rounds[0].iter().map(|m| m.players.clone()).collect();
+        assert_eq!(first_round, vec![vec![0], vec![3, 4], vec![1], vec![2, 5]]);
+        let waiting: Vec<Vec<usize>> =
+            tournament.matches_without_room().into_iter().map(|(_, players)| players).collect();
+        assert_eq!(waiting, vec![vec![3, 4], vec![2, 5]]);
+
+        // Upsets all the way: the higher seed wins
+        let highest_seed = |players: &[usize]| *players.iter().max().unwrap();
+        play_round(&mut tournament, highest_seed);
+        let second_round: Vec<Vec<usize>> =
+            tournament.rounds[1].iter().map(|m| m.players.clone()).collect();
+        assert_eq!(second_round, vec![vec![0, 4], vec![1, 5]]);
+
+        play_out(&mut tournament, highest_seed);
+        assert_eq!(tournament.round(), 3);
+        assert_eq!(tournament.progress().winner.as_deref(), Some("player-5"));
+    }
+
+    #[test]
+    fn record_ignores_unknown_and_finished_rooms() {
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 4);
+        tournament.set_room(0, MatchRoom { id: 7, token: "7".to_string() });
+        let won = results(&[("player-0", 1, 3), ("player-3", 2, 1)]);
+
+        assert!(!tournament.record(8, &won));
+        assert!(tournament.rounds[0][0].outcome.is_none());
+
+        assert!(!tournament.record(7, &won));
+        assert!(!tournament.record(7, &results(&[("player-3", 1, 9)])));
+        let outcome = tournament.rounds[0][0].outcome.as_ref().unwrap();
+        assert_eq!(outcome.winner, Some(0));
+        assert_eq!(outcome.scores, vec![3, 1]);
+    }
+
+    #[test]
+    fn record_schedules_the_next_round_once_the_round_is_over() {
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 4);
+        tournament.set_room(0, MatchRoom { id: 1, token: "1".to_string() });
+        tournament.set_room(1, MatchRoom { id: 2, token: "2".to_string() });
+
+        assert!(!tournament.record(1, &results(&[("player-0", 1, 3), ("player-3", 2, 1)])));
+        assert_eq!(tournament.round(), 1);
+        assert!(tournament.record(2, &results(&[("player-1", 1, 3), ("player-2", 2, 1)])));
+        assert_eq!(tournament.round(), 2);
+    }
+
+    #[test]
+    fn record_draws_and_no_shows() {
+        // Equal ranks make a draw, worth half a point each
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 2);
+        tournament.set_room(0, MatchRoom { id: 1, token: "1".to_string() });
+        tournament.record(1, &results(&[("player-1", 1, 2), ("player-0", 1, 2)]));
+        assert_eq!(tournament.rounds[0][0].outcome.as_ref().unwrap().winner, None);
+        assert!(tournament.standings().iter().all(|standing| standing.points == 0.5));
+
+        // Single elimination matches can't be drawn: the room's standings
+        // break the tie
+        let mut tournament = new_tournament(TournamentFormat::SingleElimination, 2);
+        tournament.set_room(0, MatchRoom { id: 1, token: "1".to_string() });
+        tournament.record(1, &results(&[("player-1", 1, 2), ("player-0", 1, 2)]));
+        assert_eq!(tournament.rounds[0][0].outcome.as_ref().unwrap().winner, Some(1));
+
+        // A player who never showed up loses, with a score of 0
+        let mut tournament = new_tournament(TournamentFormat::RoundRobin, 2);
+        tournament.set_room(0, MatchRoom { id: 1, token: "1".to_string() });
+        tournament.record(1, &results(&[("player-1", 1, 0)]));
+        let outcome = tournament.rounds[0][0].outcome.as_ref().unwrap();
+        assert_eq!(outcome.winner, Some(1));
+        assert_eq!(outcome.scores, vec![0, 0]);
+    }
+}
@@ -0,0 +1,288 @@
+use failure::{bail, err_msg, Error};
+use std::time::{Duration, Instant};
+use tokyo::{
+    models::{ClientState, CommandError, GameCommand},
+    Handler,
+};
+use wasmi::{
+    Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+// What a bot can use up on each turn, a turn being a call to `tick` or
+// `on_command_result`. Around a millisecond or two worth of instructions, so
+// that a few bots fit in a tick.
+const FUEL_PER_TURN: u64 = 1_000_000;
+const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+// Running out of fuel stops a bot in its tracks, but a turn can still be slow,
+// e.g. when it grows its memory. Bots whose ticks are too slow too many times
+// in a row get disabled so that they don't hold up the room. The results of
+// the commands sent on a tick count towards the time of that tick.
+const MAX_TURN_TIME: Duration = Duration::from_millis(5);
+const MAX_SLOW_TURNS: u32 = 3;
+
+// Longer than any command can be
+const MAX_COMMAND_BYTES: usize = 1024;
+
+/// A bot uploaded as a WebAssembly module, played by the server inside the game
+/// loop of a room, the same as a `HouseBot`. The module exports the functions
+/// described in `tokyo::wasm`, and gets no imports at all, so the only thing it
+/// can do is play. It's disabled, and stays put for the rest of the match, if it
+/// traps, runs out of fuel or is too slow.
+#[derive(Debug)]
+pub struct WasmBot {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<u32, u32>,
+    tick: TypedFunc<(u32, u32), u64>,
+    command_result: Option<TypedFunc<(u32, u32), ()>>,
+    max_turn_time: Duration,
+    turn_time: Duration, // Spent on the last tick so far, command results included
+    slow_turns: u32,
+    disabled: bool,
+}
+
+impl WasmBot {
+    /// Compiles and instantiates a module, failing if it doesn't follow the ABI.
+    pub fn new(module: &[u8]) -> Result<Self, Error> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, module)?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .instances(1)
+            .memories(1)
+            .tables(1)
+            .build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        store.add_fuel(FUEL_PER_TURN).map_err(wasmi::Error::from)?;
+
+        // Nothing is linked, a module with imports fails to instantiate
+        let instance = Linker::new(&engine).instantiate(&mut store, &module)?.start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| err_msg("The module doesn't export its memory"))?;
+        let alloc = instance.get_typed_func(&store, "tokyo_alloc")?;
+        let tick = instance.get_typed_func(&store, "tokyo_tick")?;
+        let command_result = instance.get_typed_func(&store, "tokyo_command_result").ok();
+
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            tick,
+            command_result,
+            max_turn_time: MAX_TURN_TIME,
+            turn_time: Duration::ZERO,
+            slow_turns: 0,
+            disabled: false,
+        })
+    }
+
+    /// Gives the bot a fresh allowance of fuel, then a turn, starting a new
+    /// tick or not. Disables the bot if anything goes wrong.
+    fn play<T>(
+        &mut self,
+        new_tick: bool,
+        turn: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Option<T> {
+        if self.disabled {
+            return None;
+        }
+
+        let was_slow = !new_tick && self.turn_time > self.max_turn_time;
+        let started = Instant::now();
+        let result = self.refuel().map_err(Error::from).and_then(|_| turn(self));
+
+        if new_tick {
+            self.turn_time = started.elapsed();
+        } else {
+            self.turn_time += started.elapsed();
+        }
+        // Only a tick can start over, a slow tick counts once
+        let is_slow = self.turn_time > self.max_turn_time;
+        if new_tick && !is_slow {
+            self.slow_turns = 0;
+        } else if is_slow && !was_slow {
+            self.slow_turns += 1;
+        }
+
+        match result {
+            Ok(_) if self.slow_turns >= MAX_SLOW_TURNS => {
+                warn!("Disabling a hosted bot: too slow");
+                self.disabled = true;
+                None
+            },
+            Ok(value) => Some(value),
+            Err(err) => {
+                warn!("Disabling a hosted bot: {}", err);
+                self.disabled = true;
+                None
+            },
+        }
+    }
+
+    fn refuel(&mut self) -> Result<(), wasmi::Error> {
+        let remaining = self.store.consume_fuel(0)?;
+        self.store.add_fuel(FUEL_PER_TURN - remaining)?;
+        Ok(())
+    }
+
+    /// Copies `bytes` into a buffer allocated by the bot, returning its pointer.
+    fn write(&mut self, bytes: &[u8]) -> Result<u32, Error> {
+        let ptr = self.alloc.call(&mut self.store, bytes.len() as u32)?;
+        self.memory.write(&mut self.store, ptr as usize, bytes).map_err(wasmi::Error::from)?;
+        Ok(ptr)
+    }
+
+    fn try_tick(&mut self, state: &ClientState) -> Result<Option<GameCommand>, Error> {
+        let input = serde_json::to_vec(state)?;
+        let ptr = self.write(&input)?;
+        let output = self.tick.call(&mut self.store, (ptr, input.len() as u32))?;
+
+        if output == 0 {
+            return Ok(None);
+        }
+
+        let (ptr, len) = ((output >> 32) as usize, (output & 0xffff_ffff) as usize);
+        if len > MAX_COMMAND_BYTES {
+            bail!("Command of {} bytes", len);
+        }
+        let mut command = vec![0; len];
+        self.memory.read(&self.store, ptr, &mut command).map_err(wasmi::Error::from)?;
+        Ok(Some(serde_json::from_slice(&command)?))
+    }
+
+    fn try_command_result(&mut self, result: Result<u64, CommandError>) -> Result<(), Error> {
+        if let Some(command_result) = self.command_result {
+            let input = serde_json::to_vec(&result)?;
+            let ptr = self.write(&input)?;
+            command_result.call(&mut self.store, (ptr, input.len() as u32))?;
+        }
+        Ok(())
+    }
+}
+
+impl Handler for WasmBot {
+    fn tick(&mut self, state: &ClientState) -> Option<GameCommand> {
+        self.play(true, |bot| bot.try_tick(state)).flatten()
+    }
+
+    fn on_command_result(&mut self, result: Result<u64, CommandError>) {
+        self.play(false, |bot| bot.try_command_result(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokyo::models::GameState;
+
+    fn leb128(mut value: u32) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn section(id: u8, content: &[u8]) -> Vec<u8> {
+        [&[id][..], &leb128(content.len() as u32), content].concat()
+    }
+
+    fn export(name: &str, kind: u8, index: u8) -> Vec<u8> {
+        [&leb128(name.len() as u32)[..], name.as_bytes(), &[kind, index]].concat()
+    }
+
+    /// A bot that counts down from `tick_loops` on every tick, or forever if
+    /// it's 0, and never sends a command.
+    fn module(tick_loops: u32) -> Vec<u8> {
+        let types = [
+            &[3][..],
+            &[0x60, 1, 0x7f, 1, 0x7f],       // (i32) -> i32
+            &[0x60, 2, 0x7f, 0x7f, 1, 0x7e], // (i32, i32) -> i64
+            &[0x60, 2, 0x7f, 0x7f, 0],       // (i32, i32) -> ()
+        ]
+        .concat();
+        let exports = [
+            &[4][..],
+            &export("memory", 2, 0),
+            &export("tokyo_alloc", 0, 0),
+            &export("tokyo_tick", 0, 1),
+            &export("tokyo_command_result", 0, 2),
+        ]
+        .concat();
+
+        let alloc = [0, 0x41, 0x80, 0x08, 0x0b]; // i32.const 1024
+        let countdown = if tick_loops == 0 {
+            vec![0x03, 0x40, 0x0c, 0x00, 0x0b] // loop br 0 end
+        } else {
+            [
+                &[0x41][..],
+                &leb128(tick_loops), // i32.const tick_loops
+                &[0x21, 2],          // local.set 2
+                &[0x03, 0x40],       // loop
+                &[0x20, 2, 0x41, 1, 0x6b, 0x22, 2, 0x0d, 0], // br_if (local.tee 2 (local.get 2 - 1)) 0
+                &[0x0b],                                     // end
+            ]
+            .concat()
+        };
+        let tick = [&[1, 1, 0x7f][..], &countdown, &[0x42, 0, 0x0b]].concat(); // i64.const 0
+        let command_result = [0, 0x0b];
+        let body = |code: &[u8]| [&leb128(code.len() as u32)[..], code].concat();
+        let code = [&[3][..], &body(&alloc), &body(&tick), &body(&command_result)].concat();
+
+        [
+            &b"\0asm\x01\0\0\0"[..],
+            &section(1, &types),
+            &section(3, &[3, 0, 1, 2]),
+            &section(5, &[1, 0, 1]),
+            &section(7, &exports),
+            &section(10, &code),
+        ]
+        .concat()
+    }
+
+    fn client_state() -> ClientState {
+        ClientState { id: 0, game_state: GameState::new((100.0, 100.0), 30.0) }
+    }
+
+    #[test]
+    fn plays_quick_bots() {
+        let mut bot = WasmBot::new(&module(10)).unwrap();
+        for _ in 0..10 {
+            assert!(bot.tick(&client_state()).is_none());
+            bot.on_command_result(Ok(0));
+        }
+        assert!(!bot.disabled);
+    }
+
+    #[test]
+    fn disables_bots_out_of_fuel() {
+        let mut bot = WasmBot::new(&module(0)).unwrap();
+        assert!(bot.tick(&client_state()).is_none());
+        assert!(bot.disabled);
+    }
+
+    #[test]
+    fn disables_slow_bots_with_quick_command_results() {
+        // Busy for most of its fuel, which takes way more than a millisecond
+        let mut bot = WasmBot::new(&module(FUEL_PER_TURN as u32 / 8)).unwrap();
+        bot.max_turn_time = Duration::from_millis(1);
+
+        for _ in 0..MAX_SLOW_TURNS {
+            assert!(!bot.disabled);
+            bot.tick(&client_state());
+            bot.on_command_result(Ok(0));
+        }
+        assert!(bot.disabled);
+    }
+}
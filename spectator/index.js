@@ -20,14 +20,13 @@ function drawRooms(rooms) {
   var tableHtml = "<tbody>";
   for (const room of rooms) {
     const room_id = room.id;
-    const room_token = room.token;
     const room_name = sanitizeHTML(room.name);
     const max_players = room.max_players;
 
     tableHtml += `
             <tr>
               <td class="room-id">${room_id}</td>
-              <td class="room-name"><a href="/room?room_token=${room_token}">${room_name}</a></td>
+              <td class="room-name"><a href="/room?room_id=${room_id}">${room_name}</a></td>
               <td class="room-players">Max players: ${max_players}</td>
             </tr>`;
   }
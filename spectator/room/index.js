@@ -10,15 +10,15 @@ var team_names = {};
 
 var ctx = c.getContext("2d");
 
-function connect(token, handler) {
-  if (token === undefined) {
-    websocket_status.innerText = "room_token not found";
+function connect(room_query, handler) {
+  if (room_query === undefined) {
+    websocket_status.innerText = "room_id or room_token not found";
   }
   websocket_status.innerText = "connecting...";
   websocket_status.style.borderColor = "gray";
   const isLocalServer = window.location.host.indexOf('localhost') !== -1;
   const protocol = isLocalServer ? 'ws://' : 'wss://';
-  const socket = new WebSocket(`${protocol}${window.location.host}/spectate?room_token=${room_token}`);
+  const socket = new WebSocket(`${protocol}${window.location.host}/spectate?${room_query}`);
   socket.addEventListener('open', function (event) {
     websocket_status.innerText = "connected";
     websocket_status.style.borderColor = "white";
@@ -28,7 +28,7 @@ function connect(token, handler) {
     websocket_status.innerText = "disconnected";
     websocket_status.style.borderColor = "orange";
     setTimeout(function () {
-      connect(handler);
+      connect(room_query, handler);
     }, 1000);
   });
 
@@ -168,12 +168,47 @@ class Bullet {
   }
 }
 
+class Obstacle {
+  constructor(obj) {
+    this.shape = obj.shape;
+    this.x = obj.x;
+    this.y = obj.y;
+    this.radius = obj.radius;
+    this.width = obj.width;
+    this.height = obj.height;
+    this.angle = obj.angle || 0;
+    this.reflective = obj.reflective;
+  }
+
+  draw(ctx) {
+    ctx.save()
+    ctx.translate(this.x, this.y);
+    ctx.rotate(this.angle);
+
+    let oldFill = ctx.fillStyle;
+    ctx.beginPath();
+    if (this.shape === "circle") {
+      ctx.arc(0, 0, this.radius, 0, 2 * Math.PI);
+    } else {
+      ctx.rect(-this.width / 2, -this.height / 2, this.width, this.height);
+    }
+    // Reflective obstacles are the lighter ones
+    ctx.fillStyle = this.reflective ? "#a4b0be" : "#57606f";
+    ctx.fill();
+    ctx.fillStyle = oldFill;
+
+    ctx.restore();
+  }
+}
+
 var last_drawn_scoreboard = {};
 var initCanvas = false;
-// get room_token from url
+// get room_id, or room_token for private rooms, from url
 const urlParams = new URLSearchParams(window.location.search);
-const room_token = urlParams.get('room_token');
-connect(room_token, function (json) {
+const room_query = urlParams.has('room_token')
+  ? `room_token=${encodeURIComponent(urlParams.get('room_token'))}`
+  : urlParams.has('room_id') ? `room_id=${encodeURIComponent(urlParams.get('room_id'))}` : undefined;
+connect(room_query, function (json) {
   if (json.e === "teamnames") {
     team_names = json.data;
   } else if (json.e === "state") {
@@ -199,6 +234,10 @@ connect(room_token, function (json) {
     ctx.lineTo(0, 0);
     ctx.stroke();
 
+    for (const obstacle of data.obstacles || []) {
+      new Obstacle(obstacle).draw(ctx);
+    }
+
     for (const player of data.players) {
       new Ship(player).draw(ctx);
     }
@@ -213,6 +252,10 @@ connect(room_token, function (json) {
 
     ctx.restore()
 
+    ctx.fillStyle = "#ffffff";
+    ctx.font = "16px monospace";
+    ctx.fillText(match_clock(data), 10, 20);
+
     if (JSON.stringify(data.scoreboard) !== JSON.stringify(last_drawn_scoreboard)) {
       draw_scoreboard(data.scoreboard);
       last_drawn_scoreboard = data.scoreboard;
@@ -220,6 +263,28 @@ connect(room_token, function (json) {
   }
 });
 
+function format_seconds(seconds) {
+  const whole = Math.ceil(seconds);
+  return `${Math.floor(whole / 60)}:${String(whole % 60).padStart(2, '0')}`;
+}
+
+function match_clock(data) {
+  switch (data.status) {
+    case 'new':
+      return 'Waiting for players';
+    case 'countdown':
+      return 'Starting soon';
+    case 'paused':
+      return 'Paused';
+    case 'finished':
+      return 'Game over';
+    default:
+      return data.remaining_seconds == null
+        ? format_seconds(data.elapsed_seconds)
+        : `${format_seconds(data.remaining_seconds)} left`;
+  }
+}
+
 function sanitizeHTML(text) {
   var element = document.createElement('div');
   element.innerText = text;